@@ -0,0 +1,16 @@
+use std::process::Command;
+
+// Embeds the short git hash of the build, when built from a checkout
+fn main() {
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(hash) = hash {
+        println!("cargo:rustc-env=PYROBASE_GIT_HASH={}", hash.trim());
+    }
+}
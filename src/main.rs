@@ -1,26 +1,29 @@
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    cursor,
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use rand::{rngs::StdRng, RngCore, SeedableRng};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::{HashMap, HashSet, VecDeque},
     error::Error,
     fs,
-    io,
+    io::{self, Write},
+    rc::Rc,
     time::{Duration, Instant},
 };
 use tui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Direction, Layout},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Span, Spans},
-    widgets::{Block, Borders, Paragraph},
+    widgets::{Block, Borders, Gauge, Paragraph, Wrap},
     Terminal,
 };
 
-fn show_lore() -> io::Result<()> {
-    let lore = [
+const DEFAULT_LORE: &[&str] = &[
         "In the distant future, the Earth has been ravaged by uncontrollable wildfires that have wiped out most of humanity's population and infrastructure.",
         "Amidst the chaos, a lone scientist named Dr. Aurelia Pyros, known for her pioneering work in fire-based technologies, survives.",
         "She discovers an ancient, long-buried facility known as Pyrobase, a research station once operated by an advanced civilization that mastered the art of harnessing the destructive power of fire.",
@@ -28,29 +31,85 @@ fn show_lore() -> io::Result<()> {
         "The facility holds the key to humanity’s survival—if Dr. Pyros can unlock its secrets.",
         "However, the base is scattered across a fractured landscape, and it will take significant resources, strategy, and time to reconstruct Pyrobase and rebuild civilization.",
         "Players must help Dr. Pyros gather resources, develop tools, and explore different sections of the Pyrobase, each guarded by environmental hazards, ancient technology, and ever-growing wildfires.",
-    ];
+];
+
+// The intro from lore.txt, one paragraph per line, or the built-in story
+// when there is no such file
+fn load_lore() -> io::Result<Vec<String>> {
+    match fs::read_to_string(LORE_FILE) {
+        Ok(contents) => Ok(contents.lines().map(str::to_string).collect()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            Ok(DEFAULT_LORE.iter().map(|line| line.to_string()).collect())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+fn show_lore(config: &Config) -> io::Result<()> {
+    let lore = load_lore()?;
 
     // Clear the terminal
     print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
 
-    // Display lore slowly, one line at a time
+    // Display lore slowly, one line at a time; blank lines still pause.
+    // Raw mode lets any key press print the rest at once
+    enable_raw_mode()?;
+    let mut skipped = false;
     for line in &lore {
-        println!("{}", line);
-        std::thread::sleep(Duration::from_millis(500)); // Adjust the delay as needed
+        print!("{}\r\n", line);
+        io::stdout().flush()?;
+        if !skipped {
+            skipped = key_pressed_within(Duration::from_millis(config.lore_delay_ms))?;
+        }
     }
+    disable_raw_mode()?;
 
     Ok(())
 }
 
+// Waits out the delay unless a key is pressed first, ignoring other events
+fn key_pressed_within(delay: Duration) -> io::Result<bool> {
+    let deadline = Instant::now() + delay;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() || !event::poll(remaining)? {
+            return Ok(false);
+        }
+        if let Event::Key(_) = event::read()? {
+            return Ok(true);
+        }
+    }
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
-    // Show lore at the start of the game
-    show_lore()?;
+    let config = Config::load()?;
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let flag = |name: &str| args.iter().any(|arg| arg == name);
+    let seed = match args.iter().position(|arg| arg == "--seed") {
+        Some(i) => args
+            .get(i + 1)
+            .and_then(|seed| seed.parse::<u64>().ok())
+            .ok_or("--seed needs a non-negative whole number")?,
+        None => rand::random(),
+    };
+    let difficulty = match args.iter().position(|arg| arg == "--difficulty") {
+        Some(i) => args
+            .get(i + 1)
+            .and_then(|name| Difficulty::by_name(&name.to_lowercase()))
+            .ok_or_else(|| format!("--difficulty needs one of: {}", DIFFICULTIES.join(", ")))?,
+        None => Difficulty::default(),
+    };
+    install_panic_hook();
 
-    // Wait for user input to proceed
-    println!("Press Enter to continue...");
-    let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
+    if !flag("--skip-lore") {
+        // Show lore at the start of the game
+        show_lore(&config)?;
 
+        // Wait for user input to proceed
+        println!("Press Enter to continue...");
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+    }
 
     // Setup terminal
     enable_raw_mode()?;
@@ -61,6 +120,21 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // Create app and run it
     let mut app = App::new(); // Directly create a new app
+    app.config = config;
+    app.history = load_history();
+    app.set_max_messages(app.config.max_messages);
+    app.reseed(seed);
+    app.difficulty = difficulty;
+    if flag("--no-color") || app.config.no_color {
+        app.no_color = true;
+        app.add_message("No-color mode: colors and cursor blinking are disabled.", MessageColor::White);
+    }
+    if flag("--cheats") {
+        app.cheats = true;
+        app.add_message("Cheats enabled: 'reveal' puts every location on the map.", MessageColor::Magenta);
+    }
+
+    app.open_menu();
 
     let res = run_app(&mut terminal, app);
 
@@ -80,12 +154,524 @@ fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-#[derive(Serialize, Deserialize)]
+// Puts the terminal back to normal before the panic message prints, so a
+// crash inside the game doesn't leave the shell in raw mode
+fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let _ = disable_raw_mode();
+        let _ = execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture, cursor::Show);
+        default_hook(info);
+    }));
+}
+
+const LEGACY_SAVE_FILE: &str = "pyrobase_save.json"; // Single save from before slots, read as slot 1
+const SAVE_SLOTS: u32 = 5;
+const AUTOSAVE_FILE: &str = "pyrobase_autosave.json";
+const AUTOSAVE_NOTICE_TICKS: u64 = 20; // How long the status bar shows "autosaved"
+const IDLE_AFTER: Duration = Duration::from_secs(5); // No input for this long counts as idle
+const IDLE_POLL: Duration = Duration::from_millis(500); // Input poll timeout while idle
+const SAVE_VERSION: u32 = 2; // Saves without a version field are version 1
+const LOG_FILE: &str = "pyrobase_log.txt";
+const HISTORY_FILE: &str = "pyrobase_history.txt";
+const MAX_HISTORY: usize = 500;
+const MAX_UNDO: usize = 10;
+// Commands that can change the game, the only ones worth an undo snapshot;
+// 'run' and 'repeat' leave that to the commands they run
+const UNDOABLE_COMMANDS: &[&str] = &["gather", "drop", "craft", "build", "trade", "go", "rest", "note", "alias", "reveal"];
+// Short forms left out of the command list, which aliases can't take over
+const SHORTHANDS: &[(&str, &str)] = &[("q", "quit"), ("examine", "look"), ("!!", "repeat")];
+const CONFIG_FILE: &str = "pyrobase_config.toml";
+const LORE_FILE: &str = "lore.txt";
+const DEFAULT_MAX_MESSAGES: usize = 1000;
+const MIN_MESSAGES: usize = 10; // Smallest log 'logsize' allows
+const VISIBLE_MESSAGES: usize = 10;
+const MAX_EVENTS: usize = 100;
+const EVENT_PANEL_HEIGHT: u16 = 5; // Three events plus borders
+const STALE_MESSAGE_TICKS: u64 = 600; // Messages older than this are drawn dimmed
+const TYPEWRITER_CHARS_PER_TICK: usize = 3;
+const TYPEWRITER_MAX_PENDING: usize = 3; // Half-revealed messages allowed before the rest are shown at once
+const MAX_PASTE_LINES: usize = 50;
+const MAX_INPUT_LEN: usize = 256;
+const MAX_COMPOSE_LEN: usize = 2000; // Longer limit for multi-line journal entries
+const COMPOSE_HEIGHT: u16 = 10; // Input box rows while composing
+const MAX_SUGGESTIONS: usize = 5;
+const ERROR_FLASH_TICKS: u64 = 2; // How long the input border stays red after a failed command
+const COMMAND_COLUMNS: usize = 4; // Columns in the 'commands' listing
+const MAX_SEARCH_RESULTS: usize = 20;
+const DEFAULT_HISTORY_LISTING: usize = 10; // Entries 'cmdhistory' shows without a count
+const MAX_RUN_DEPTH: usize = 3; // How deeply 'run' scripts may run other scripts
+const MAX_ROLLS: u64 = 10_000_000; // Far beyond any real game; loading replays every draw
+const MAX_HEALTH: u32 = 100;
+const FIRE_ENTRY_DAMAGE: u32 = 10;
+const CRITICAL_HEALTH: u32 = 20; // Health at or below which the bell rings
+const MAX_ENERGY: u32 = 100;
+const GATHER_ENERGY: u32 = 10;
+const MOVE_ENERGY: u32 = 5;
+const ENERGY_REGEN_INTERVAL: u64 = 10; // Ticks per point of energy recovered
+const MAX_REST_HOURS: u64 = 12;
+const HAZARD_INTERVAL: u64 = 20; // Ticks between burns while standing in high fire
+const MAX_FIRE: u8 = 10;
+const HIGH_FIRE: u8 = 7; // Fire level at which a location starts hurting the player
+const FIRE_SPREAD_INTERVAL: u64 = 100; // Ticks between wildfire spread steps
+const MINIMAP_WIDTH: u16 = 15;
+const CONSTRUCTION_BAR_WIDTH: usize = 20;
+const MIN_TERMINAL_WIDTH: u16 = 40;
+const MIN_TERMINAL_HEIGHT: u16 = 10;
+const FULL_LAYOUT_HEIGHT: u16 = 17; // Shorter terminals drop the events panel to keep the log and input usable
+const MAX_WEIGHT: u32 = 200; // Carrying capacity
+const TOOL_WEIGHT: u32 = 5; // Weight of each crafted item
+const DIRECTIONS: &[&str] = &["north", "south", "east", "west"];
+const RESOURCES: &[&str] = &[
+    "firestone",
+    "emberash",
+    "heatcores",
+    "sulfur_ore",
+    "charcoal_essence",
+    "ashen_dust",
+];
+
+// How many units one 'gather' can yield; rarer resources come in smaller lumps
+fn gather_range(resource: &str) -> (u32, u32) {
+    match resource {
+        "heatcores" => (1, 4),
+        "firestone" | "sulfur_ore" => (3, 7),
+        _ => (4, 8),
+    }
+}
+
+// A count typed as a command argument, which must be a whole number of at
+// least 1; the error is ready to show the player
+fn parse_amount(arg: &str) -> Result<u32, String> {
+    let digits = arg.strip_prefix('-').unwrap_or(arg);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return Err(format!("'{}' is not a valid number.", arg));
+    }
+    if arg.starts_with('-') {
+        return Err(format!("'{}' is negative; amounts start at 1.", arg));
+    }
+    match arg.parse::<u32>() {
+        Ok(0) => Err("The amount must be at least 1.".to_string()),
+        Ok(amount) => Ok(amount),
+        Err(_) => Err(format!("'{}' is too large.", arg)),
+    }
+}
+
+// Fixed exchange rates: (give, amount given, get, amount received)
+const TRADES: &[(&str, u32, &str, u32)] = &[
+    ("emberash", 3, "ashen_dust", 1),
+    ("firestone", 2, "sulfur_ore", 1),
+    ("ashen_dust", 2, "charcoal_essence", 1),
+    ("sulfur_ore", 3, "heatcores", 1),
+    ("charcoal_essence", 4, "heatcores", 1),
+];
+
+// Weight of one unit of an inventory item; crafted tools all weigh the same
+fn item_weight(item: &str) -> u32 {
+    match item {
+        "firestone" | "sulfur_ore" => 2,
+        "heatcores" => 3,
+        "emberash" | "charcoal_essence" | "ashen_dust" => 1,
+        _ => TOOL_WEIGHT,
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+struct Config {
+    tick_rate_ms: u64,
+    ticks_per_hour: u64, // Game clock speed, lower values fast-forward time
+    lore_delay_ms: u64, // Pause after each line of the intro
+    autosave_interval: u64, // Ticks between autosaves, 0 turns them off
+    max_messages: usize, // Message log length before the oldest are dropped
+    bell: bool, // Ring the terminal bell on low health and achievements
+    timestamps: bool, // Show seconds since the start beside each message
+    no_color: bool, // Like --no-color, but remembered
+    typewriter: bool, // Reveal new messages a few characters per tick
+    cursor: CursorStyle,
+    cursor_blink: bool,
+    keybindings: Keybindings,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            tick_rate_ms: 50, // Increased update frequency
+            ticks_per_hour: 200,
+            lore_delay_ms: 500,
+            autosave_interval: 0,
+            max_messages: DEFAULT_MAX_MESSAGES,
+            bell: false,
+            timestamps: false,
+            no_color: false,
+            typewriter: false,
+            cursor: CursorStyle::Underscore,
+            cursor_blink: true,
+            keybindings: Keybindings::default(),
+        }
+    }
+}
+
+const CURSOR_STYLES: &[&str] = &["block", "underscore", "bar", "none"];
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum CursorStyle {
+    Block,
+    Underscore,
+    Bar,
+    None,
+}
+
+impl CursorStyle {
+    fn by_name(name: &str) -> Option<CursorStyle> {
+        match name {
+            "block" => Some(CursorStyle::Block),
+            "underscore" => Some(CursorStyle::Underscore),
+            "bar" => Some(CursorStyle::Bar),
+            "none" => Some(CursorStyle::None),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            CursorStyle::Block => "block",
+            CursorStyle::Underscore => "underscore",
+            CursorStyle::Bar => "bar",
+            CursorStyle::None => "none",
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            CursorStyle::Block => "█",
+            CursorStyle::Underscore => "_",
+            CursorStyle::Bar => "▏",
+            CursorStyle::None => "",
+        }
+    }
+}
+
+const DIFFICULTIES: &[&str] = &["easy", "normal", "hard"];
+
+// Chosen at startup and kept with the save; scales how fast the wildfire
+// spreads, how much gathering yields and how much effort costs
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Default)]
+enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    fn by_name(name: &str) -> Option<Difficulty> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    fn fire_spread_interval(self) -> u64 {
+        match self {
+            Difficulty::Easy => FIRE_SPREAD_INTERVAL * 2,
+            Difficulty::Normal => FIRE_SPREAD_INTERVAL,
+            Difficulty::Hard => FIRE_SPREAD_INTERVAL / 2,
+        }
+    }
+
+    // Percentages of the normal gather yield and energy cost
+    fn yield_percent(self) -> u32 {
+        match self {
+            Difficulty::Easy => 150,
+            Difficulty::Normal => 100,
+            Difficulty::Hard => 75,
+        }
+    }
+
+    fn energy_percent(self) -> u32 {
+        match self {
+            Difficulty::Easy => 75,
+            Difficulty::Normal => 100,
+            Difficulty::Hard => 150,
+        }
+    }
+
+    fn gather_range(self, resource: &str) -> (u32, u32) {
+        let (min, max) = gather_range(resource);
+        (scale(min, self.yield_percent()), scale(max, self.yield_percent()))
+    }
+
+    fn energy_cost(self, cost: u32) -> u32 {
+        scale(cost, self.energy_percent())
+    }
+}
+
+// A percentage of `value`, never rounding down to nothing
+fn scale(value: u32, percent: u32) -> u32 {
+    (value * percent / 100).max(1)
+}
+
+// Keys for the remappable actions, written like "enter", "f2" or "ctrl-p"
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+struct Keybindings {
+    submit: String,
+    complete: String,
+    history_up: String,
+    history_down: String,
+}
+
+impl Default for Keybindings {
+    fn default() -> Keybindings {
+        Keybindings {
+            submit: "enter".to_string(),
+            complete: "tab".to_string(),
+            history_up: "up".to_string(),
+            history_down: "down".to_string(),
+        }
+    }
+}
+
+impl Keybindings {
+    // Each action with its configured key and the built-in key it stands in for
+    fn actions(&self) -> [(&'static str, &str, KeyCode); 4] {
+        [
+            ("submit", &self.submit, KeyCode::Enter),
+            ("complete", &self.complete, KeyCode::Tab),
+            ("history_up", &self.history_up, KeyCode::Up),
+            ("history_down", &self.history_down, KeyCode::Down),
+        ]
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (action, key, _) in self.actions() {
+            if parse_key(key).is_none() {
+                return Err(format!("invalid key '{}' for {} in {}", key, action, CONFIG_FILE));
+            }
+        }
+        Ok(())
+    }
+
+    // Rewrites a bound key into the built-in key for its action, so the input
+    // handling only ever sees the defaults
+    fn translate(&self, key: KeyEvent) -> KeyEvent {
+        let pressed = (key.code, key.modifiers - KeyModifiers::SHIFT);
+        self.actions()
+            .into_iter()
+            .find(|(_, binding, _)| parse_key(binding) == Some(pressed))
+            .map(|(_, _, code)| KeyEvent::new(code, KeyModifiers::NONE))
+            .unwrap_or(key)
+    }
+}
+
+// Parses a key description such as "enter", "f2", "x" or "ctrl-alt-k"
+fn parse_key(description: &str) -> Option<(KeyCode, KeyModifiers)> {
+    let description = description.trim().to_lowercase();
+    let mut rest = description.as_str();
+    let mut modifiers = KeyModifiers::NONE;
+    loop {
+        if let Some(key) = rest.strip_prefix("ctrl-") {
+            modifiers |= KeyModifiers::CONTROL;
+            rest = key;
+        } else if let Some(key) = rest.strip_prefix("alt-") {
+            modifiers |= KeyModifiers::ALT;
+            rest = key;
+        } else {
+            break;
+        }
+    }
+    let code = match rest {
+        "enter" => KeyCode::Enter,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "esc" => KeyCode::Esc,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "space" => KeyCode::Char(' '),
+        key if key.chars().count() == 1 => KeyCode::Char(key.chars().next()?),
+        key => {
+            let number = key.strip_prefix('f')?.parse::<u8>().ok()?;
+            if !(1..=12).contains(&number) {
+                return None;
+            }
+            KeyCode::F(number)
+        }
+    };
+    Some((code, modifiers))
+}
+
+impl Config {
+    // A missing config file just means the defaults are used
+    fn load() -> Result<Config, Box<dyn Error>> {
+        match fs::read_to_string(CONFIG_FILE) {
+            Ok(contents) => {
+                let config: Config = toml::from_str(&contents)?;
+                config.keybindings.validate()?;
+                Ok(config)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    // Writes every setting back out, for preferences changed in game
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        fs::write(CONFIG_FILE, toml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Recipe {
+    inputs: HashMap<String, u32>,
+    output: String,
+}
+
+fn default_recipes() -> Vec<Recipe> {
+    let recipe = |output: &str, inputs: &[(&str, u32)]| Recipe {
+        inputs: inputs.iter().map(|(name, amount)| (name.to_string(), *amount)).collect(),
+        output: output.to_string(),
+    };
+    vec![
+        recipe("blaze_hammer", &[("firestone", 10), ("emberash", 5)]),
+        recipe("molten_cutter", &[("firestone", 5), ("heatcores", 5), ("sulfur_ore", 5)]),
+        recipe("pyrodrill", &[("heatcores", 10), ("sulfur_ore", 10), ("charcoal_essence", 5)]),
+        recipe("fire_manipulator", &[("charcoal_essence", 10), ("ashen_dust", 10), ("heatcores", 5)]),
+        recipe("phoenix_beacon", &[("heatcores", 20), ("charcoal_essence", 20), ("ashen_dust", 20)]),
+    ]
+}
+
+// Something built over several game hours, possibly only after another
+// structure is finished
+#[derive(Clone, Debug)]
+struct Structure {
+    name: String,
+    inputs: HashMap<String, u32>,
+    hours: u64,
+    requires: Option<String>,
+}
+
+fn default_structures() -> Vec<Structure> {
+    let structure = |name: &str, inputs: &[(&str, u32)], hours, requires: Option<&str>| Structure {
+        name: name.to_string(),
+        inputs: inputs.iter().map(|(name, amount)| (name.to_string(), *amount)).collect(),
+        hours,
+        requires: requires.map(str::to_string),
+    };
+    vec![
+        structure("shelter", &[("firestone", 10), ("emberash", 10)], 2, None),
+        structure("forge", &[("firestone", 20), ("heatcores", 5)], 4, Some("shelter")),
+        structure("watchtower", &[("sulfur_ore", 10), ("charcoal_essence", 10)], 6, Some("shelter")),
+        structure("flame_spire", &[("heatcores", 15), ("ashen_dust", 15)], 12, Some("forge")),
+    ]
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct Construction {
+    name: String,
+    ticks_remaining: u64,
+    total_ticks: u64,
+}
+
+// Where the day started, so its summary can report what changed since
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct DayLog {
+    gathered_before: u64,
+    fire_before: u32,
+    health_before: u32,
+    visited: HashSet<String>, // Locations entered during the day
+}
+
+impl Default for DayLog {
+    fn default() -> DayLog {
+        DayLog {
+            gathered_before: 0,
+            fire_before: initial_fire_levels().values().map(|level| u32::from(*level)).sum(),
+            health_before: MAX_HEALTH,
+            visited: HashSet::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 enum AppState {
-    Game, // Remove Home state
+    Menu, // Title screen shown before play starts, never saved
+    Settings, // Options screen over the menu or the game, never saved
+    Game,
+    GameOver,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MenuItem {
+    NewGame,
+    Continue(u32), // Save slot to load
+    Settings,
+    Quit,
+}
+
+// What the settings screen can toggle; each is kept in the config file
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Setting {
+    Timestamps,
+    Colors,
+    Sound,
+    Typewriter,
+}
+
+const SETTINGS: &[Setting] = &[Setting::Timestamps, Setting::Colors, Setting::Sound, Setting::Typewriter];
+
+impl Setting {
+    fn label(self) -> &'static str {
+        match self {
+            Setting::Timestamps => "Message timestamps",
+            Setting::Colors => "Colors",
+            Setting::Sound => "Bell on alerts",
+            Setting::Typewriter => "Typewriter effect",
+        }
+    }
+}
+
+impl MenuItem {
+    fn label(self) -> String {
+        match self {
+            MenuItem::NewGame => "New Game".to_string(),
+            MenuItem::Continue(slot) => format!("Continue (slot {})", slot),
+            MenuItem::Settings => "Settings".to_string(),
+            MenuItem::Quit => "Quit".to_string(),
+        }
+    }
+}
+
+// What the main loop should do after a command has run
+#[derive(Debug, PartialEq)]
+enum CommandResult {
+    Continue,
+    Quit,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+// Runs one command given its arguments as typed. Shared rather than boxed so
+// the dispatcher can hold on to a handler while it borrows the app mutably
+type CommandHandler = Rc<dyn Fn(&mut App, &[String]) -> CommandResult>;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 enum MessageColor {
     Red,
     Green,
@@ -93,10 +679,28 @@ enum MessageColor {
     Blue,
     Cyan,
     White,
+    Magenta,
+    Gray,
+    Rgb(u8, u8, u8),
 }
 
 impl MessageColor {
-    fn to_color(&self) -> Color {
+    // Named colors only; fire shades are RGB and have no name
+    fn by_name(name: &str) -> Option<MessageColor> {
+        match name.to_lowercase().as_str() {
+            "red" => Some(MessageColor::Red),
+            "green" => Some(MessageColor::Green),
+            "yellow" => Some(MessageColor::Yellow),
+            "blue" => Some(MessageColor::Blue),
+            "cyan" => Some(MessageColor::Cyan),
+            "white" => Some(MessageColor::White),
+            "magenta" => Some(MessageColor::Magenta),
+            "gray" => Some(MessageColor::Gray),
+            _ => None,
+        }
+    }
+
+    fn to_color(self) -> Color {
         match self {
             MessageColor::Red => Color::Red,
             MessageColor::Green => Color::Green,
@@ -104,206 +708,5251 @@ impl MessageColor {
             MessageColor::Blue => Color::Blue,
             MessageColor::Cyan => Color::Cyan,
             MessageColor::White => Color::White,
+            MessageColor::Magenta => Color::Magenta,
+            MessageColor::Gray => Color::Gray,
+            MessageColor::Rgb(r, g, b) => Color::Rgb(r, g, b),
         }
     }
 }
 
-// Runtime version of Message
-struct Message {
-    content: String,
-    color: MessageColor,
-    timestamp: Instant,
-}
-
-// Storage version of Message
-#[derive(Serialize, Deserialize, Clone)]
-struct StoredMessage {
-    content: String,
-    color: MessageColor,
-}
+const MESSAGE_COLORS: &[&str] = &["red", "green", "yellow", "blue", "cyan", "white", "magenta", "gray"];
+const THEMES: &[&str] = &["default", "mono", "highcontrast"];
+const PALETTES: &[&str] = &["default", "deuteranopia", "protanopia"];
 
-#[derive(Serialize, Deserialize)]
-struct App {
-    state: AppState,
-    input: String,
-    last_command: String,
-    commands: Vec<String>,
-    messages: Vec<StoredMessage>,
-    message_index: usize,  // Track position in ring buffer
+// Message hues for color vision deficiencies, applied on top of a theme
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+enum Palette {
+    #[default]
+    Default,
+    Deuteranopia,
+    Protanopia,
 }
 
-impl App {
-    fn new() -> App {
-        App {
-            state: AppState::Game, // Directly start in Game state
-            input: String::new(),
-            last_command: String::new(),
-            commands: vec![
-                "quit".to_string()
-            ],
-            messages: vec![
-                StoredMessage {
-                    content: "Welcome to Pyrobase. Type 'help' for commands.".to_string(),
-                    color: MessageColor::Yellow,
-                },
-                StoredMessage {
-                    content: "Type 'quit' to exit the game.".to_string(),
-                    color: MessageColor::Cyan,
-                },
-            ],
-            message_index: 0,
+impl Palette {
+    fn by_name(name: &str) -> Option<Palette> {
+        match name {
+            "default" => Some(Palette::Default),
+            "deuteranopia" => Some(Palette::Deuteranopia),
+            "protanopia" => Some(Palette::Protanopia),
+            _ => None,
         }
     }
 
-    fn get_autocomplete_suggestions(&self) -> Vec<String> {
-        self.commands
-            .iter()
-            .filter(|cmd| cmd.starts_with(&self.input))
-            .cloned()
-            .collect()
+    fn name(self) -> &'static str {
+        match self {
+            Palette::Default => "default",
+            Palette::Deuteranopia => "deuteranopia",
+            Palette::Protanopia => "protanopia",
+        }
     }
 
-    fn add_message(&mut self, content: &str, color: MessageColor) {
-        if self.messages.len() >= 1000 {
-            // Use ring buffer behavior
-            self.message_index = (self.message_index + 1) % 1000;
-            if let Some(msg) = self.messages.get_mut(self.message_index) {
-                msg.content = content.to_string();
-                msg.color = color;
-            } else {
-                self.messages.push(StoredMessage {
-                    content: content.to_string(),
-                    color,
-                });
-            }
-        } else {
-            self.messages.push(StoredMessage {
-                content: content.to_string(),
-                color,
-            });
-            self.message_index = self.messages.len() - 1;
+    // The whole remapping lives here: errors become orange or amber and
+    // successes blue, so red and green never have to be told apart
+    fn remap(self, color: MessageColor) -> MessageColor {
+        match (self, color) {
+            (Palette::Deuteranopia, MessageColor::Red) => MessageColor::Rgb(230, 159, 0),
+            (Palette::Deuteranopia, MessageColor::Green) => MessageColor::Rgb(0, 114, 178),
+            (Palette::Deuteranopia, MessageColor::Blue) => MessageColor::Rgb(86, 180, 233),
+            (Palette::Deuteranopia, MessageColor::Yellow) => MessageColor::Rgb(240, 228, 66),
+            (Palette::Protanopia, MessageColor::Red) => MessageColor::Rgb(255, 176, 0),
+            (Palette::Protanopia, MessageColor::Green) => MessageColor::Rgb(0, 158, 255),
+            (Palette::Protanopia, MessageColor::Yellow) => MessageColor::Rgb(240, 240, 160),
+            (Palette::Protanopia, MessageColor::Magenta) => MessageColor::Rgb(204, 121, 167),
+            (_, color) => color,
         }
     }
+}
 
-    fn show_help(&mut self) {
-        self.add_message("Available commands:", MessageColor::Cyan);
-        self.add_message("quit - exit the game", MessageColor::Cyan);
-    }
+// Only the theme and palette names are persisted, as "name" or
+// "name:palette"; the colors are rebuilt from them on load
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(from = "String", into = "String")]
+struct Theme {
+    name: String,
+    foreground: Color,
+    background: Color,
+    border: Color,
+    monochrome: bool,
+    colorless: bool, // Leaves every color to the terminal, for --no-color
+    palette: Palette,
 }
 
-fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<()> {
-    let tick_rate = Duration::from_millis(50); // Increased update frequency
-    let mut last_tick = Instant::now();
-    loop {
-        terminal.draw(|f| ui(f, &app))?;
+impl Theme {
+    fn by_name(name: &str) -> Option<Theme> {
+        let (foreground, background, border, monochrome) = match name {
+            "default" => (Color::White, Color::Reset, Color::White, false),
+            "mono" => (Color::Gray, Color::Reset, Color::Gray, true),
+            "highcontrast" => (Color::White, Color::Black, Color::Yellow, false),
+            _ => return None,
+        };
+        Some(Theme {
+            name: name.to_string(),
+            foreground,
+            background,
+            border,
+            monochrome,
+            colorless: false,
+            palette: Palette::Default,
+        })
+    }
 
-        let timeout = tick_rate
-            .checked_sub(last_tick.elapsed())
-            .unwrap_or_else(|| Duration::from_secs(0));
+    // Used instead of the chosen theme when colors are switched off
+    fn plain() -> Theme {
+        Theme {
+            name: "plain".to_string(),
+            foreground: Color::Reset,
+            background: Color::Reset,
+            border: Color::Reset,
+            monochrome: true,
+            colorless: true,
+            palette: Palette::Default,
+        }
+    }
 
-        if crossterm::event::poll(timeout)? {
-            if let Event::Key(key) = event::read()? {
-                match app.state {
-                    AppState::Game => match key.code {
-                        KeyCode::Char(c) => {
-                            app.input.push(c);
-                        }
-                        KeyCode::Backspace => {
-                            app.input.pop();
-                        }
-                        KeyCode::Enter => {
-                            app.last_command = app.input.clone();
-                            match app.input.trim().to_lowercase().as_str() {
-                                "q" | "quit" => {
-                                    return Ok(());
-                                }
-                                "help" => {
-                                    app.show_help();
-                                }
-                                "" => {}
-                                _ => {
-                                    app.add_message("Unknown command. Type 'help' for commands.", MessageColor::Red);
-                                }
-                            }
-                            app.input.clear();
-                        }
-                        _ => {}
-                    },
-                }
-            }
+    fn message_color(&self, color: MessageColor) -> Color {
+        if self.colorless {
+            return Color::Reset;
         }
+        if self.monochrome {
+            // Keep warnings and errors slightly brighter than everything else
+            return match color {
+                MessageColor::Red | MessageColor::Yellow => Color::White,
+                _ => Color::Gray,
+            };
+        }
+        self.palette.remap(color).to_color()
+    }
 
-        if last_tick.elapsed() >= tick_rate {
-            terminal.draw(|f| ui(f, &app))?; // Force redraw every tick
-            last_tick = Instant::now();
+    fn message_style(&self, msg: &StoredMessage) -> Style {
+        let style = Style::default().fg(self.message_color(msg.color));
+        match msg.bg {
+            // Grays on grays would be unreadable, so monochrome drops backgrounds
+            Some(bg) if !self.monochrome => style.bg(self.message_color(bg)),
+            _ => style,
         }
     }
-}
 
-fn ui<B: Backend>(f: &mut tui::Frame<B>, app: &App) {
-    match app.state {
-        AppState::Game => {
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .margin(1)
-                .constraints(
-                    [
-                        Constraint::Percentage(80), // Adjusted to close the gap
-                        Constraint::Percentage(20), // Adjusted to close the gap
-                    ]
-                    .as_ref(),
-                )
-                .split(f.size());
+    fn text(&self) -> Style {
+        Style::default().fg(self.foreground).bg(self.background)
+    }
 
-            let status_and_messages = vec![
-                Spans::from(Span::styled(
-                    "Welcome to Pyrobase. Type 'help' for commands.",
-                    Style::default().fg(Color::Yellow),
-                )),
-                Spans::from(""),
-            ];
+    // A fixed UI color that isn't a message color, dropped when colorless
+    fn accent(&self, color: Color) -> Color {
+        if self.colorless {
+            Color::Reset
+        } else {
+            color
+        }
+    }
 
-            let visible_messages = app.messages.iter()
-                .rev()
-                .take(10)
-                .map(|msg| {
-                    Spans::from(vec![
-                        Span::styled(
-                            format!("> {}", msg.content),
-                            Style::default().fg(msg.color.to_color())
-                        )
-                    ])
-                })
-                .collect::<Vec<_>>();
+    fn cursor(&self) -> Style {
+        if self.colorless {
+            self.text()
+        } else {
+            self.text().add_modifier(Modifier::SLOW_BLINK)
+        }
+    }
 
-            let combined_content = status_and_messages.into_iter().chain(visible_messages).collect::<Vec<_>>();
+    fn block<'a>(&self, title: &'a str) -> Block<'a> {
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(self.border))
+            .title(title)
+    }
+}
 
-            let status_and_messages_widget = Paragraph::new(combined_content)
-                .block(Block::default().borders(Borders::ALL).title("Status and Messages"))
-                .style(Style::default().fg(Color::White));
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme::by_name("default").unwrap()
+    }
+}
 
-            f.render_widget(status_and_messages_widget, chunks[0]);
+impl From<String> for Theme {
+    fn from(name: String) -> Theme {
+        let (name, palette) = name.split_once(':').unwrap_or((&name, ""));
+        let mut theme = Theme::by_name(name).unwrap_or_default();
+        theme.palette = Palette::by_name(palette).unwrap_or_default();
+        theme
+    }
+}
 
-            let suggestions = if app.input.is_empty() {
-                "".to_string()
-            } else {
-                let suggestions = app.get_autocomplete_suggestions().join(", ");
-                format!(" [{}]", suggestions)
-            };
+impl From<Theme> for String {
+    fn from(theme: Theme) -> String {
+        match theme.palette {
+            Palette::Default => theme.name,
+            palette => format!("{}:{}", theme.name, palette.name()),
+        }
+    }
+}
 
-            let cursor = Span::styled("_", Style::default().fg(Color::White).add_modifier(Modifier::SLOW_BLINK));
-            let input_content = vec![
-                Span::raw("> "),
-                Span::raw(&app.input),
-                cursor,
-                Span::raw(suggestions),
-            ];
+// Storage version of Message, timestamped by game tick since `Instant` can't be saved
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct StoredMessage {
+    content: String,
+    color: MessageColor,
+    #[serde(default)]
+    tick: u64,
+    #[serde(default)]
+    bold: bool,
+    #[serde(default)]
+    italic: bool,
+    #[serde(default)]
+    underline: bool,
+    #[serde(default)]
+    dim: bool,
+    #[serde(default = "default_count")]
+    count: u32, // How many identical messages in a row this entry stands for
+    #[serde(default)]
+    bg: Option<MessageColor>,
+    #[serde(skip)]
+    hidden: usize, // Characters at the end still waiting for the typewriter
+}
 
-            let input_widget = Paragraph::new(Spans::from(input_content))
-                .block(Block::default().borders(Borders::ALL).title("Input"))
-                .style(Style::default().fg(Color::White));
+fn default_count() -> u32 {
+    1
+}
 
-            f.render_widget(input_widget, chunks[1]);
+impl StoredMessage {
+    fn new(content: &str, color: MessageColor, tick: u64) -> StoredMessage {
+        StoredMessage {
+            content: content.to_string(),
+            color,
+            tick,
+            bold: false,
+            italic: false,
+            underline: false,
+            dim: false,
+            count: 1,
+            bg: None,
+            hidden: 0,
         }
     }
-}
\ No newline at end of file
+
+    // Same text drawn the same way, so the two can share one entry
+    fn repeats(&self, other: &StoredMessage) -> bool {
+        self.content == other.content
+            && self.color == other.color
+            && self.bg == other.bg
+            && self.modifiers() == other.modifiers()
+    }
+
+    fn modifiers(&self) -> Modifier {
+        let mut modifiers = Modifier::empty();
+        if self.bold {
+            modifiers |= Modifier::BOLD;
+        }
+        if self.italic {
+            modifiers |= Modifier::ITALIC;
+        }
+        if self.underline {
+            modifiers |= Modifier::UNDERLINED;
+        }
+        if self.dim {
+            modifiers |= Modifier::DIM;
+        }
+        modifiers
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct App {
+    #[serde(default)]
+    version: u32,
+    state: AppState,
+    input: String,
+    #[serde(skip)]
+    cursor_pos: usize, // Insertion point in `input`, in characters
+    last_command: String,
+    #[serde(skip, default = "command_registry")]
+    commands: HashMap<String, CommandHandler>, // name -> what running it does
+    messages: Vec<StoredMessage>,
+    message_index: usize,  // Track position in ring buffer
+    #[serde(default = "default_max_messages")]
+    max_messages: usize, // Ring buffer capacity, saved so the order can be rebuilt
+    #[serde(default)]
+    events: Vec<StoredMessage>, // Game-driven notifications, kept apart from command output
+    #[serde(skip)]
+    history: Vec<String>, // Kept in its own file so it survives loads and restarts
+    #[serde(skip)]
+    history_index: Option<usize>, // Position while browsing history with Up/Down
+    #[serde(default)]
+    inventory: HashMap<String, u32>,
+    #[serde(default)]
+    tick_count: u64,
+    #[serde(skip)]
+    dirty: bool, // Unsaved changes since the last save or load
+    #[serde(skip)]
+    changed: bool, // Set when the command being handled touches game state
+    #[serde(skip)]
+    undo_stack: Vec<String>, // JSON snapshots taken before each state-changing command
+    #[serde(skip)]
+    quit_armed: bool,
+    #[serde(skip)]
+    pending_confirm: Option<String>, // Destructive command waiting for a y/n answer
+    #[serde(skip)]
+    confirmed: bool, // Set while running a command the player just confirmed
+    #[serde(skip)]
+    scroll_offset: usize, // Messages hidden below the view, 0 shows the newest
+    #[serde(skip)]
+    filter: Option<MessageColor>, // Only messages of this color are shown
+    #[serde(skip, default = "default_page_height")]
+    page_height: usize, // Rows available to the message log, updated on resize
+    #[serde(skip)]
+    screen_size: (u16, u16), // Last known terminal size, for relayouts
+    #[serde(skip)]
+    composing: bool, // Multi-line journal entry mode
+    #[serde(skip)]
+    menu: Vec<MenuItem>,
+    #[serde(skip)]
+    menu_selected: usize,
+    #[serde(skip)]
+    settings_selected: usize, // Index into SETTINGS, one past the end for "Back"
+    #[serde(skip)]
+    settings_from: Option<AppState>, // Where leaving the settings screen returns to
+    #[serde(default = "default_location")]
+    location: String,
+    #[serde(skip, default = "location_descriptions")]
+    descriptions: HashMap<String, String>,
+    #[serde(skip, default = "command_help")]
+    help: HashMap<String, (String, String)>, // command -> (short description, long help)
+    #[serde(skip, default = "world_map")]
+    map: HashMap<String, HashMap<String, String>>, // location -> direction -> destination
+    #[serde(skip, default = "map_coordinates")]
+    coordinates: HashMap<String, (i32, i32)>, // location -> (x, y) with north at smaller y
+    #[serde(default = "default_show_minimap")]
+    show_minimap: bool,
+    #[serde(default)]
+    theme: Theme,
+    #[serde(skip)]
+    config: Config,
+    #[serde(default = "default_aliases")]
+    aliases: HashMap<String, String>, // short -> full command
+    #[serde(default = "initial_fire_levels")]
+    fire_levels: HashMap<String, u8>,
+    #[serde(default = "default_health")]
+    health: u32,
+    #[serde(default = "default_energy")]
+    energy: u32,
+    #[serde(skip, default = "default_recipes")]
+    recipes: Vec<Recipe>,
+    #[serde(skip)]
+    input_overflow: bool, // Flashes the input border until the next UI tick
+    #[serde(skip)]
+    errors_logged: u64, // Red messages so far, to tell whether a command failed
+    #[serde(skip)]
+    last_error_tick: Option<u64>, // UI tick when the last command failed, for the border flash
+    #[serde(skip)]
+    ui_ticks: u64, // Keeps counting while the game is paused or over, for UI effects
+    #[serde(skip)]
+    search: Option<String>, // Query while in Ctrl-R reverse history search
+    #[serde(skip)]
+    no_color: bool, // Set by --no-color, overrides the theme while drawing
+    #[serde(skip)]
+    cheats: bool, // Set by --cheats, enables the hidden 'reveal' command
+    #[serde(skip)]
+    bell_pending: bool, // Rung by run_app after the next draw
+    #[serde(skip)]
+    autosaved_at: Option<u64>, // Tick of the last autosave
+    #[serde(skip)]
+    paused: bool, // Stops the simulation from ticking
+    #[serde(skip)]
+    lore_queue: VecDeque<String>, // Intro lines 'lore' has yet to reveal
+    #[serde(skip)]
+    run_depth: usize, // Scripts currently being run by 'run'
+    #[serde(skip)]
+    repeating: bool, // Inside 'repeat', which runs a command the player didn't type again
+    #[serde(skip)]
+    next_lore_tick: u64,
+    #[serde(default)]
+    commands_entered: u64,
+    #[serde(default)]
+    resources_gathered: u64,
+    #[serde(default = "default_visited")]
+    visited: HashSet<String>,
+    #[serde(default)]
+    notes: Vec<String>,
+    #[serde(default)]
+    today: DayLog,
+    #[serde(default)]
+    constructions: Vec<Construction>, // Builds still under way, in the order started
+    #[serde(default)]
+    structures: HashSet<String>, // Finished builds
+    #[serde(default)]
+    achievements: HashSet<String>,
+    #[serde(default)]
+    played_secs: u64, // Real playtime over every session, as of the last save
+    #[serde(skip, default = "Instant::now")]
+    start: Instant, // When this session began
+    #[serde(skip, default = "Instant::now")]
+    playtime_since: Instant, // Playtime after this is not yet in played_secs
+    #[serde(default)]
+    difficulty: Difficulty,
+    #[serde(default)]
+    seed: u64,
+    #[serde(default)]
+    rolls: u64, // Numbers drawn since seeding, so a load can resume the sequence
+    #[serde(skip, default = "default_rng")]
+    rng: StdRng,
+}
+
+fn default_rng() -> StdRng {
+    StdRng::seed_from_u64(0)
+}
+
+struct Achievement {
+    name: &'static str,
+    description: &'static str,
+    earned: fn(&App) -> bool,
+}
+
+fn achievement_list() -> Vec<Achievement> {
+    let achievement = |name, description, earned| Achievement { name, description, earned };
+    vec![
+        achievement("First Gather", "gather your first resource", |app| app.resources_gathered > 0),
+        achievement("Explorer", "visit 5 locations", |app| app.visited.len() >= 5),
+        achievement("Survivor", "reach day 3", |app| app.game_time().0 >= 3),
+    ]
+}
+
+fn default_max_messages() -> usize {
+    DEFAULT_MAX_MESSAGES
+}
+
+fn default_visited() -> HashSet<String> {
+    HashSet::from([default_location()])
+}
+
+fn default_health() -> u32 {
+    MAX_HEALTH
+}
+
+fn default_energy() -> u32 {
+    MAX_ENERGY
+}
+
+fn initial_fire_levels() -> HashMap<String, u8> {
+    world_map()
+        .into_keys()
+        .map(|location| {
+            let level = if location == "Inferno Wells" { MAX_FIRE } else { 0 };
+            (location, level)
+        })
+        .collect()
+}
+
+// Green when mostly full, red when nearly empty
+fn threshold_color(current: u32, max: u32) -> MessageColor {
+    match current * 10 / max.max(1) {
+        6.. => MessageColor::Green,
+        3..=5 => MessageColor::Yellow,
+        _ => MessageColor::Red,
+    }
+}
+
+fn fire_color(level: u8) -> MessageColor {
+    match level {
+        0..=2 => MessageColor::Green,
+        3..=5 => MessageColor::Yellow,
+        6..=7 => MessageColor::Rgb(255, 140, 0),
+        _ => MessageColor::Red,
+    }
+}
+
+fn default_aliases() -> HashMap<String, String> {
+    [("i", "inventory"), ("l", "look")]
+        .into_iter()
+        .map(|(short, full)| (short.to_string(), full.to_string()))
+        .collect()
+}
+
+fn default_page_height() -> usize {
+    VISIBLE_MESSAGES
+}
+
+fn default_location() -> String {
+    "Entrance".to_string()
+}
+
+// For the many commands that never end the game
+fn command(run: impl Fn(&mut App, &[String]) + 'static) -> CommandHandler {
+    Rc::new(move |app, args| {
+        run(app, args);
+        CommandResult::Continue
+    })
+}
+
+// Arguments are case-insensitive except file names and notes, which keep the
+// case they were typed with, so handlers lowercase what they need
+fn first_arg(args: &[String]) -> String {
+    args.first().map(|arg| arg.to_lowercase()).unwrap_or_default()
+}
+
+fn lowercase_args(args: &[String]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_lowercase()).collect()
+}
+
+// Every command the dispatcher knows; new commands only need an entry here
+// and one in command_help
+fn command_registry() -> HashMap<String, CommandHandler> {
+    let quit: CommandHandler = Rc::new(|app: &mut App, _: &[String]| {
+        if app.request_quit() {
+            CommandResult::Quit
+        } else {
+            CommandResult::Continue
+        }
+    });
+    let run: CommandHandler = Rc::new(|app: &mut App, args: &[String]| {
+        app.run_file(args.first().map(String::as_str).unwrap_or(""))
+    });
+    let repeat: CommandHandler = Rc::new(|app: &mut App, _: &[String]| app.repeat_last());
+    [
+        ("help", command(|app, args| app.show_help(&first_arg(args)))),
+        ("commands", command(|app, _| app.list_commands())),
+        ("gather", command(|app, args| app.gather(&first_arg(args)))),
+        ("inventory", command(|app, _| app.show_inventory())),
+        ("drop", command(|app, args| app.drop_item(&lowercase_args(args)))),
+        ("craft", command(|app, args| app.craft(&first_arg(args)))),
+        ("recipes", command(|app, _| app.show_recipes())),
+        ("build", command(|app, args| app.build(&first_arg(args)))),
+        ("trade", command(|app, args| app.trade(&lowercase_args(args)))),
+        ("trades", command(|app, _| app.show_trades())),
+        ("construction", command(|app, _| app.show_construction())),
+        ("look", command(|app, _| app.look())),
+        ("go", command(|app, args| app.go(&first_arg(args)))),
+        ("fire", command(|app, _| app.report_fire())),
+        ("clear", command(|app, _| app.clear_messages())),
+        ("export", command(|app, args| app.export_log(args.first().map(String::as_str).unwrap_or("")))),
+        ("run", run),
+        ("stats", command(|app, _| app.show_stats())),
+        ("version", command(|app, _| app.show_version())),
+        ("time", command(|app, _| app.show_time())),
+        ("achievements", command(|app, _| app.show_achievements())),
+        ("note", command(|app, args| app.note(args))),
+        ("notes", command(|app, _| app.show_notes())),
+        ("compose", command(|app, _| app.start_compose())),
+        ("search", command(|app, args| app.search_log(&lowercase_args(args).join(" ")))),
+        ("lore", command(|app, _| app.replay_lore())),
+        ("pause", command(|app, _| app.set_paused(true))),
+        ("resume", command(|app, _| app.set_paused(false))),
+        ("filter", command(|app, args| app.set_filter(&first_arg(args)))),
+        ("timestamps", command(|app, _| app.toggle_timestamps())),
+        ("settings", command(|app, _| app.open_settings())),
+        ("bell", command(|app, _| app.toggle_bell())),
+        ("typewriter", command(|app, _| app.toggle_typewriter())),
+        ("cmdhistory", command(|app, args| app.show_command_history(&first_arg(args)))),
+        ("logsize", command(|app, args| app.set_log_size(&first_arg(args)))),
+        ("map", command(|app, _| app.toggle_minimap())),
+        ("theme", command(|app, args| app.set_theme(&lowercase_args(args)))),
+        ("cursor", command(|app, args| app.set_cursor(&lowercase_args(args)))),
+        ("alias", command(|app, args| app.alias(&lowercase_args(args)))),
+        ("undo", command(|app, _| app.undo())),
+        ("repeat", repeat),
+        ("rest", command(|app, args| app.rest(&first_arg(args)))),
+        ("restart", command(|app, _| app.restart())),
+        ("save", command(|app, args| app.save_game(&first_arg(args)))),
+        ("load", command(|app, args| app.load_game(&first_arg(args)))),
+        ("saves", command(|app, _| app.show_saves())),
+        ("quit", quit),
+    ]
+    .into_iter()
+    .map(|(name, handler)| (name.to_string(), handler))
+    .collect()
+}
+
+fn command_help() -> HashMap<String, (String, String)> {
+    [
+        ("help", "show available commands", "'help' lists every command; 'help <command>' explains one in detail."),
+        ("commands", "list command names", "'commands' lists every command and alias name in alphabetical columns, without descriptions."),
+        ("gather", "collect a resource", "'gather <resource>' adds some of a resource to your inventory, e.g. 'gather firestone'."),
+        ("inventory", "list held resources", "'inventory' lists every resource you are carrying, sorted by name."),
+        ("drop", "discard carried items", "'drop <item> [amount]' throws away some of an item, or all of it, to free up carrying capacity. 'drop all' empties your whole inventory once you confirm with y."),
+        ("build", "construct a structure", "'build <structure>' uses up the resources a shelter, forge, watchtower or flame_spire needs and starts building it; some need another structure finished first. Building takes hours of game time."),
+        ("construction", "show building progress", "'construction' shows a progress bar for each structure being built and lists the finished ones."),
+        ("trade", "exchange resources", "'trade <resource> <amount> for <resource>' swaps resources at a fixed rate, in whole lots; for example 'trade emberash 6 for ashen_dust'."),
+        ("trades", "list exchange rates", "'trades' lists every exchange 'trade' accepts and its rate."),
+        ("craft", "turn resources into a tool", "'craft <item>' uses up the resources a recipe needs and adds the item to your inventory."),
+        ("recipes", "list craftable items", "'recipes' lists every item that can be crafted and what it costs."),
+        ("look", "describe your surroundings", "'look' (or 'examine') describes the section of Pyrobase you are standing in and lists the directions you can go from it."),
+        ("go", "move to another location", "'go <direction>' moves north, south, east or west if a passage leads that way."),
+        ("fire", "check the local wildfire", "'fire' reports how intensely the current location is burning, from 0 to 10."),
+        ("clear", "wipe the message log", "'clear' removes every message from the log, after asking you to confirm with y."),
+        ("run", "run commands from a file", "'run <file>' runs every line of a text file as a command; blank lines and lines starting with '#' are skipped, and so are commands that would ask for confirmation."),
+        ("export", "write the message log to a file", "'export [file]' writes every logged message to a text file, pyrobase_log.txt by default."),
+        ("time", "show real playtime", "'time' shows how long you have played this session and across every saved session, next to the in-game clock."),
+        ("achievements", "list earned achievements", "'achievements' lists the milestones you have reached this game, such as visiting 5 locations."),
+        ("version", "show which build this is", "'version' shows the game version, the git commit it was built from when known, and the save format version. Include it when reporting a bug."),
+        ("stats", "summarize this game", "'stats' shows commands entered, game time, messages logged, resources gathered, locations visited and the game's random seed."),
+        ("note", "write a journal entry", "'note <text>' adds an entry to your journal; 'note clear' erases every entry."),
+        ("compose", "write a long journal entry", "'compose' opens a taller input box for a multi-line journal entry; Enter starts a new line, Ctrl-D saves it and Esc discards it."),
+        ("notes", "read your journal", "'notes' lists your journal entries, numbered from oldest to newest."),
+        ("pause", "freeze the simulation", "'pause' stops time, the wildfire and energy recovery while you read; commands still work."),
+        ("resume", "unfreeze the simulation", "'resume' lets time run again after 'pause'."),
+        ("lore", "replay the intro story", "'lore' retells the story of Pyrobase in the message log, one line at a time."),
+        ("search", "find text in the message log", "'search <term>' lists the 20 most recent messages containing <term>, ignoring case."),
+        ("filter", "show one message color", "'filter <color>' hides every message not of that color, e.g. 'filter red'; 'filter off' shows them all again."),
+        ("logsize", "set how many messages are kept", "'logsize <n>' keeps the last n messages (at least 10) in the log, dropping older ones, and saves the setting to the config file; 'logsize' alone shows the current size."),
+        ("cmdhistory", "list recent commands", "'cmdhistory [n]' lists the last n commands you typed (10 by default), the same ones Up and Down step through."),
+        ("typewriter", "toggle typed-out messages", "'typewriter' turns on or off revealing new messages a few characters at a time, and saves the setting to the config file."),
+        ("bell", "toggle audible alerts", "'bell' turns the terminal bell for low health and achievements on or off, and saves the setting to the config file."),
+        ("timestamps", "toggle message timestamps", "'timestamps' toggles showing the seconds since the game started beside each message, and saves the setting to the config file."),
+        ("settings", "open the settings screen", "'settings' opens a screen for turning timestamps, colors, the bell and the typewriter effect on or off; Up and Down move, Enter or Space toggles, Esc goes back. Changes are saved to the config file."),
+        ("map", "toggle the minimap", "'map' shows or hides the minimap, where '@' marks you, '#' explored rooms and '?' unexplored ones."),
+        ("cursor", "change the input cursor", "'cursor <style>' picks a block, underscore, bar or none cursor; 'cursor blink <on|off>' turns blinking on or off. Both are saved to the config file."),
+        ("theme", "switch color theme", "'theme <name>' switches between the default, mono and highcontrast themes; 'theme palette <name>' picks the default, deuteranopia or protanopia message colors."),
+        ("alias", "create a command shortcut", "'alias <short> <command>' makes <short> run <command>; 'alias' alone lists every alias."),
+        ("repeat", "run the last command again", "'repeat', or '!!', runs the previous command again exactly as it was typed."),
+        ("undo", "revert the last change", "'undo' reverts the last command that changed the game, up to 10 steps back."),
+        ("rest", "recover energy", "'rest [hours]' lets 1 to 12 hours pass (1 by default) while you recover the energy that gathering and moving use up, though the wildfire keeps spreading."),
+        ("restart", "start a new game", "'restart' abandons the current game and starts over from the Entrance, after asking you to confirm with y."),
+        ("save", "save the game to disk", "'save [slot]' writes the current game to slot 1 to 5 (1 by default), stored as pyrobase_save_<slot>.json."),
+        ("load", "load a saved game", "'load [slot]' restores the game saved in slot 1 to 5 (1 by default)."),
+        ("saves", "list save slots", "'saves' shows which save slots are in use and when each was last written."),
+        ("quit", "exit the game", "'quit' (or 'q', Esc or Ctrl-C) exits the game, asking for confirmation if there are unsaved changes."),
+    ]
+    .into_iter()
+    .map(|(name, short, long)| (name.to_string(), (short.to_string(), long.to_string())))
+    .collect()
+}
+
+fn world_map() -> HashMap<String, HashMap<String, String>> {
+    let connections = [
+        ("Entrance", "north", "Scorched Plains"),
+        ("Scorched Plains", "east", "Ember Fields"),
+        ("Scorched Plains", "north", "Forgeflame Ruins"),
+        ("Forgeflame Ruins", "east", "Inferno Wells"),
+        ("Inferno Wells", "north", "Pyro Nexus"),
+    ];
+    let mut map: HashMap<String, HashMap<String, String>> = HashMap::new();
+    for (from, direction, to) in connections {
+        map.entry(from.to_string())
+            .or_default()
+            .insert(direction.to_string(), to.to_string());
+        map.entry(to.to_string())
+            .or_default()
+            .insert(opposite_direction(direction).to_string(), from.to_string());
+    }
+    map
+}
+
+fn map_coordinates() -> HashMap<String, (i32, i32)> {
+    [
+        ("Entrance", (0, 3)),
+        ("Scorched Plains", (0, 2)),
+        ("Ember Fields", (1, 2)),
+        ("Forgeflame Ruins", (0, 1)),
+        ("Inferno Wells", (1, 1)),
+        ("Pyro Nexus", (1, 0)),
+    ]
+    .into_iter()
+    .map(|(name, position)| (name.to_string(), position))
+    .collect()
+}
+
+fn default_show_minimap() -> bool {
+    true
+}
+
+fn opposite_direction(direction: &str) -> &'static str {
+    match direction {
+        "north" => "south",
+        "south" => "north",
+        "east" => "west",
+        _ => "east",
+    }
+}
+
+fn location_descriptions() -> HashMap<String, String> {
+    [
+        ("Entrance", "A blast door hangs off its hinges. Soot-streaked corridors lead deeper into Pyrobase."),
+        ("Scorched Plains", "Blackened grass crunches underfoot and firestone glints among the cinders."),
+        ("Ember Fields", "Smouldering furrows stretch to the horizon, thick with drifting emberash."),
+        ("Forgeflame Ruins", "The shells of ancient forges still radiate heat from deep within."),
+        ("Inferno Wells", "Shafts of molten light rise from wells bored into the bedrock."),
+        ("Pyro Nexus", "A vast chamber where every conduit of the base converges on a single white flame."),
+    ]
+    .into_iter()
+    .map(|(name, description)| (name.to_string(), description.to_string()))
+    .collect()
+}
+
+impl App {
+    fn new() -> App {
+        App {
+            version: SAVE_VERSION,
+            state: AppState::Game, // main opens the menu; restarts go straight back into play
+            input: String::new(),
+            cursor_pos: 0,
+            last_command: String::new(),
+            commands: command_registry(),
+            messages: vec![
+                StoredMessage::new("Welcome to Pyrobase. Type 'help' for commands.", MessageColor::Yellow, 0),
+                StoredMessage::new("Type 'quit' to exit the game.", MessageColor::Cyan, 0),
+            ],
+            message_index: 1,
+            max_messages: DEFAULT_MAX_MESSAGES,
+            events: Vec::new(),
+            history: Vec::new(),
+            history_index: None,
+            inventory: HashMap::new(),
+            tick_count: 0,
+            dirty: false,
+            changed: false,
+            undo_stack: Vec::new(),
+            quit_armed: false,
+            pending_confirm: None,
+            confirmed: false,
+            scroll_offset: 0,
+            filter: None,
+            page_height: default_page_height(),
+            screen_size: (0, 0),
+            composing: false,
+            menu: Vec::new(),
+            menu_selected: 0,
+            settings_selected: 0,
+            settings_from: None,
+            location: default_location(),
+            descriptions: location_descriptions(),
+            help: command_help(),
+            map: world_map(),
+            coordinates: map_coordinates(),
+            show_minimap: default_show_minimap(),
+            theme: Theme::default(),
+            config: Config::default(),
+            aliases: default_aliases(),
+            fire_levels: initial_fire_levels(),
+            health: default_health(),
+            energy: default_energy(),
+            recipes: default_recipes(),
+            input_overflow: false,
+            errors_logged: 0,
+            last_error_tick: None,
+            ui_ticks: 0,
+            search: None,
+            no_color: false,
+            cheats: false,
+            bell_pending: false,
+            autosaved_at: None,
+            paused: false,
+            lore_queue: VecDeque::new(),
+            run_depth: 0,
+            repeating: false,
+            next_lore_tick: 0,
+            commands_entered: 0,
+            resources_gathered: 0,
+            visited: default_visited(),
+            notes: Vec::new(),
+            today: DayLog::default(),
+            constructions: Vec::new(),
+            structures: HashSet::new(),
+            achievements: HashSet::new(),
+            played_secs: 0,
+            start: Instant::now(),
+            playtime_since: Instant::now(),
+            difficulty: Difficulty::default(),
+            seed: 0,
+            rolls: 0,
+            rng: default_rng(),
+        }
+    }
+
+    // The most recent history entry containing the search query
+    fn search_match(&self) -> Option<&String> {
+        let query = self.search.as_deref().filter(|query| !query.is_empty())?;
+        self.history.iter().rev().find(|entry| entry.contains(query))
+    }
+
+    fn search_key(&mut self, key: KeyEvent) {
+        let Some(query) = self.search.as_mut() else {
+            return;
+        };
+        match key.code {
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => query.push(c),
+            KeyCode::Backspace => {
+                query.pop();
+            }
+            KeyCode::Esc => self.search = None,
+            _ => {}
+        }
+    }
+
+    // Leave search mode with the found command in the input, ready to execute
+    fn accept_search(&mut self) {
+        if self.search.is_some() {
+            self.set_input(self.search_match().cloned().unwrap_or_default());
+            self.search = None;
+        }
+    }
+
+    // Replaces the whole line, leaving the cursor at its end
+    fn set_input(&mut self, text: String) {
+        self.cursor_pos = text.chars().count();
+        self.input = text;
+    }
+
+    fn take_input(&mut self) -> String {
+        self.cursor_pos = 0;
+        std::mem::take(&mut self.input)
+    }
+
+    fn cursor(&self) -> usize {
+        self.cursor_pos.min(self.input.chars().count())
+    }
+
+    // Byte offset of the cursor, for slicing `input`
+    fn cursor_byte(&self) -> usize {
+        self.input.char_indices().nth(self.cursor()).map_or(self.input.len(), |(i, _)| i)
+    }
+
+    fn push_input(&mut self, c: char) {
+        let limit = if self.composing { MAX_COMPOSE_LEN } else { MAX_INPUT_LEN };
+        if self.input.chars().count() >= limit {
+            self.input_overflow = true;
+            return;
+        }
+        let at = self.cursor_byte();
+        self.input.insert(at, c);
+        self.cursor_pos = self.cursor() + 1;
+    }
+
+    // Deletes the character before the cursor
+    fn backspace(&mut self) {
+        let cursor = self.cursor();
+        if cursor > 0 {
+            self.cursor_pos = cursor - 1;
+            let at = self.cursor_byte();
+            self.input.remove(at);
+        }
+    }
+
+    // Deletes the character under the cursor
+    fn delete_forward(&mut self) {
+        let at = self.cursor_byte();
+        if at < self.input.len() {
+            self.input.remove(at);
+        }
+    }
+
+    fn move_cursor(&mut self, by: isize) {
+        self.cursor_pos = self.cursor().saturating_add_signed(by).min(self.input.chars().count());
+    }
+
+    // Shell-style Ctrl-W: drops the word before the cursor and any spaces after it
+    fn delete_word(&mut self) {
+        let end = self.cursor_byte();
+        let trimmed = self.input[..end].trim_end();
+        let start = trimmed
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        self.input.replace_range(start..end, "");
+        self.cursor_pos = self.input[..start].chars().count();
+    }
+
+    // The input line split around the cursor
+    fn input_spans(&self, theme: &Theme) -> Vec<Span<'_>> {
+        let (before, after) = self.input.split_at(self.cursor_byte());
+        vec![Span::raw(before), self.cursor_span(theme), Span::raw(after)]
+    }
+
+    // Shows the title menu, offering to continue from the newest save
+    fn open_menu(&mut self) {
+        self.menu = [
+            Some(MenuItem::NewGame),
+            latest_save_slot().map(MenuItem::Continue),
+            Some(MenuItem::Settings),
+            Some(MenuItem::Quit),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        self.menu_selected = 0;
+        self.state = AppState::Menu;
+    }
+
+    fn menu_key(&mut self, key: KeyEvent) -> CommandResult {
+        let count = self.menu.len().max(1);
+        match key.code {
+            KeyCode::Up => self.menu_selected = (self.menu_selected + count - 1) % count,
+            KeyCode::Down => self.menu_selected = (self.menu_selected + 1) % count,
+            KeyCode::Enter => match self.menu.get(self.menu_selected).copied() {
+                Some(MenuItem::NewGame) => {
+                    self.state = AppState::Game;
+                    // The menu doesn't count as time played
+                    self.playtime_since = Instant::now();
+                }
+                Some(MenuItem::Continue(slot)) => {
+                    self.state = AppState::Game;
+                    self.load_game(&slot.to_string());
+                }
+                Some(MenuItem::Settings) => self.open_settings(),
+                Some(MenuItem::Quit) => return CommandResult::Quit,
+                None => {}
+            },
+            _ => {}
+        }
+        CommandResult::Continue
+    }
+
+    fn start_compose(&mut self) {
+        self.composing = true;
+        self.take_input();
+        self.relayout();
+        self.add_message(
+            "Composing a journal entry: Enter starts a new line, Ctrl-D saves, Esc cancels.",
+            MessageColor::Cyan,
+        );
+    }
+
+    fn compose_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => self.finish_compose(true),
+            KeyCode::Esc => self.finish_compose(false),
+            KeyCode::Enter => self.push_input('\n'),
+            KeyCode::Char(c) => self.push_input(c),
+            KeyCode::Backspace => self.backspace(),
+            _ => {}
+        }
+    }
+
+    fn finish_compose(&mut self, save: bool) {
+        let text = self.take_input();
+        self.composing = false;
+        self.relayout();
+        let text = text.trim_end();
+        if !save {
+            self.add_message("Journal entry discarded.", MessageColor::Yellow);
+        } else if text.trim().is_empty() {
+            self.add_message("Nothing to save.", MessageColor::Yellow);
+        } else {
+            self.notes.push(text.to_string());
+            self.mark_changed();
+            self.add_message("Journal entry saved.", MessageColor::Green);
+        }
+    }
+
+    // Fixes anything a hand-edited or corrupted save could get wrong that the
+    // rest of the game relies on, returning what had to be repaired
+    fn validate(&mut self) -> Vec<&'static str> {
+        let mut repairs = Vec::new();
+        if self.message_index >= self.messages.len().max(1) {
+            self.message_index = self.messages.len().saturating_sub(1);
+            repairs.push("message position");
+        }
+        if self.max_messages < MIN_MESSAGES {
+            self.max_messages = MIN_MESSAGES;
+            repairs.push("log size");
+        }
+        if !self.map.contains_key(&self.location) {
+            self.location = default_location();
+            repairs.push("location");
+        }
+        if self.health > MAX_HEALTH || self.energy > MAX_ENERGY {
+            self.health = self.health.min(MAX_HEALTH);
+            self.energy = self.energy.min(MAX_ENERGY);
+            repairs.push("health and energy");
+        }
+        if self.fire_levels.values().any(|level| *level > MAX_FIRE) {
+            self.fire_levels.values_mut().for_each(|level| *level = (*level).min(MAX_FIRE));
+            repairs.push("fire levels");
+        }
+        if self.constructions.iter().any(|construction| construction.ticks_remaining > construction.total_ticks) {
+            for construction in &mut self.constructions {
+                construction.ticks_remaining = construction.ticks_remaining.min(construction.total_ticks);
+            }
+            repairs.push("construction progress");
+        }
+        if self.today.gathered_before > self.resources_gathered {
+            self.today.gathered_before = self.resources_gathered;
+            repairs.push("day summary");
+        }
+        if self.rolls > MAX_ROLLS {
+            self.rolls = MAX_ROLLS;
+            repairs.push("random number state");
+        }
+        if self.health == 0 && self.state == AppState::Game {
+            self.state = AppState::GameOver;
+            repairs.push("game over");
+        }
+        repairs
+    }
+
+    // Swap in a whole new game while keeping settings that belong to this session
+    fn replace_with(&mut self, other: App) {
+        let config = std::mem::take(&mut self.config);
+        let history = std::mem::take(&mut self.history);
+        let undo_stack = std::mem::take(&mut self.undo_stack);
+        let page_height = self.page_height;
+        let screen_size = self.screen_size;
+        let start = self.start;
+        let max_messages = self.max_messages;
+        let (no_color, cheats, run_depth) = (self.no_color, self.cheats, self.run_depth);
+        *self = other;
+        self.config = config;
+        self.history = history;
+        self.undo_stack = undo_stack;
+        self.page_height = page_height;
+        self.screen_size = screen_size;
+        self.start = start;
+        self.no_color = no_color;
+        self.cheats = cheats;
+        self.run_depth = run_depth;
+        self.restore_rng();
+        self.set_max_messages(max_messages);
+    }
+
+    fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rolls = 0;
+        self.restore_rng();
+    }
+
+    // The generator itself isn't saved, so rebuild it from the seed and
+    // replay the draws already made
+    fn restore_rng(&mut self) {
+        self.rng = StdRng::seed_from_u64(self.seed);
+        for _ in 0..self.rolls {
+            self.rng.next_u64();
+        }
+    }
+
+    // A number from min to max inclusive; always exactly one draw, which is
+    // what lets restore_rng replay the sequence
+    fn roll(&mut self, (min, max): (u32, u32)) -> u32 {
+        self.rolls += 1;
+        min + (self.rng.next_u64() % u64::from(max - min + 1)) as u32
+    }
+
+    // Records unsaved progress that 'undo' can also step back over
+    fn mark_changed(&mut self) {
+        self.dirty = true;
+        self.changed = true;
+    }
+
+    fn undo(&mut self) {
+        let Some(snapshot) = self.undo_stack.pop() else {
+            self.add_message("Nothing to undo.", MessageColor::Yellow);
+            return;
+        };
+        match serde_json::from_str::<App>(&snapshot) {
+            Ok(previous) => {
+                // The log is kept as is so the undone command stays visible;
+                // marking dirty directly keeps undo from snapshotting itself
+                let messages = std::mem::take(&mut self.messages);
+                let message_index = self.message_index;
+                let played_secs = self.total_playtime();
+                // Settings and screen state take no snapshot of their own, so
+                // they carry over instead of rolling back with the game
+                let theme = self.theme.clone();
+                let (paused, show_minimap, composing, filter) = (self.paused, self.show_minimap, self.composing, self.filter);
+                let search = self.search.take();
+                let lore_queue = std::mem::take(&mut self.lore_queue);
+                let next_lore_tick = self.next_lore_tick;
+                self.replace_with(previous);
+                self.messages = messages;
+                self.message_index = message_index;
+                self.played_secs = played_secs;
+                self.theme = theme;
+                self.paused = paused;
+                self.show_minimap = show_minimap;
+                self.composing = composing;
+                self.filter = filter;
+                self.search = search;
+                self.lore_queue = lore_queue;
+                self.next_lore_tick = next_lore_tick.min(self.tick_count);
+                self.dirty = true;
+                self.add_message("Undid the last change.", MessageColor::Yellow);
+            }
+            Err(err) => self.add_message(&format!("Failed to undo: {}", err), MessageColor::Red),
+        }
+    }
+
+    fn damage(&mut self, amount: u32) {
+        if self.state == AppState::GameOver {
+            return;
+        }
+        let before = self.health;
+        self.health = self.health.saturating_sub(amount);
+        self.mark_changed();
+        if before > CRITICAL_HEALTH && self.health <= CRITICAL_HEALTH {
+            if self.health > 0 {
+                self.add_message_bg("Your health is critical!", MessageColor::White, MessageColor::Red);
+            }
+            self.ring_bell();
+        }
+        if self.health == 0 {
+            self.state = AppState::GameOver;
+            self.add_bold_message("You have perished in the flames.", MessageColor::Red);
+            self.add_message("Type 'restart' to begin again or 'quit' to exit.", MessageColor::Yellow);
+        }
+    }
+
+    fn restart(&mut self) {
+        // Seeded from the old game so a seeded run stays reproducible
+        let mut game = App::new();
+        game.seed = self.rng.next_u64();
+        game.difficulty = self.difficulty;
+        self.replace_with(game);
+        // Nothing from the old expedition can be undone into the new one, so
+        // this starts a fresh stack instead of marking a change to snapshot
+        self.undo_stack.clear();
+        self.dirty = true;
+        self.add_message("A new expedition into Pyrobase begins.", MessageColor::Green);
+    }
+
+    // Advance the simulation by one tick
+    fn tick(&mut self) {
+        // The world stops with the player; nothing burns, builds or dawns after death
+        if self.state != AppState::Game {
+            return;
+        }
+        let (day, _) = self.game_time();
+        self.tick_count += 1;
+        if self.game_time().0 > day {
+            self.summarize_day(day);
+            self.add_event(&format!("Day {} dawns.", day + 1), MessageColor::Yellow);
+        }
+        self.advance_typewriter();
+        self.reveal_lore();
+        self.check_achievements();
+        if self.tick_count.is_multiple_of(ENERGY_REGEN_INTERVAL) {
+            self.energy = (self.energy + 1).min(MAX_ENERGY);
+        }
+        if self.tick_count.is_multiple_of(self.difficulty.fire_spread_interval()) {
+            self.spread_fire();
+        }
+        self.advance_constructions();
+        let level = self.fire_level();
+        if level >= HIGH_FIRE && self.tick_count.is_multiple_of(HAZARD_INTERVAL) {
+            self.add_message("The heat sears you.", MessageColor::Red);
+            self.damage(u32::from(level - HIGH_FIRE) + 1);
+        }
+    }
+
+    fn fire_total(&self) -> u32 {
+        self.fire_levels.values().map(|level| u32::from(*level)).sum()
+    }
+
+    // Reports how the day that just ended went and starts tracking the next
+    fn summarize_day(&mut self, day: u32) {
+        let visited = self.today.visited.len();
+        let summary = format!(
+            "═══ Day {}: gathered {}, visited {} location{}, fire {:+}, health {:+} ═══",
+            day,
+            self.resources_gathered.saturating_sub(self.today.gathered_before),
+            visited,
+            if visited == 1 { "" } else { "s" },
+            i64::from(self.fire_total()) - i64::from(self.today.fire_before),
+            i64::from(self.health) - i64::from(self.today.health_before),
+        );
+        self.push_event(StoredMessage {
+            bold: true,
+            ..StoredMessage::new(&summary, MessageColor::Cyan, self.tick_count)
+        });
+        self.today = DayLog {
+            gathered_before: self.resources_gathered,
+            fire_before: self.fire_total(),
+            health_before: self.health,
+            visited: HashSet::new(),
+        };
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        if self.paused == paused {
+            let state = if paused { "already paused" } else { "not paused" };
+            self.add_message(&format!("The game is {}.", state), MessageColor::Yellow);
+            return;
+        }
+        self.paused = paused;
+        if paused {
+            self.add_message("Game paused. Type 'resume' to continue.", MessageColor::Yellow);
+        } else {
+            self.add_message("Game resumed.", MessageColor::Green);
+        }
+    }
+
+    fn replay_lore(&mut self) {
+        match load_lore() {
+            Ok(lines) => {
+                self.lore_queue = lines.into();
+                self.next_lore_tick = self.tick_count;
+            }
+            Err(err) => self.add_message(&format!("Failed to read lore: {}", err), MessageColor::Red),
+        }
+    }
+
+    // Logs the next line of a lore replay once its delay has passed, pacing
+    // lines like the intro does; blank lines only add a pause
+    fn reveal_lore(&mut self) {
+        if self.tick_count < self.next_lore_tick {
+            return;
+        }
+        let Some(line) = self.lore_queue.pop_front() else {
+            return;
+        };
+        if !line.is_empty() {
+            self.add_message(&line, MessageColor::Magenta);
+        }
+        let delay = self.config.lore_delay_ms / self.config.tick_rate_ms.max(1);
+        self.next_lore_tick = self.tick_count + delay.max(1);
+    }
+
+    // Takes the energy an action costs, or refuses if there isn't enough
+    fn spend_energy(&mut self, cost: u32) -> bool {
+        if self.energy < cost {
+            self.add_message("Too tired. Type 'rest' to recover.", MessageColor::Red);
+            return false;
+        }
+        self.energy -= cost;
+        true
+    }
+
+    // Lets game time pass, recovering energy while the wildfire keeps
+    // spreading, then sums up what changed
+    fn rest(&mut self, hours: &str) {
+        if self.paused {
+            self.add_message("Time is frozen while paused. Type 'resume' first.", MessageColor::Red);
+            return;
+        }
+        let hours = if hours.is_empty() { Ok(1) } else { parse_amount(hours) };
+        let hours = match hours.map(u64::from) {
+            Ok(hours) if hours <= MAX_REST_HOURS => hours,
+            Ok(_) => {
+                self.add_message(&format!("Usage: rest [hours], from 1 to {}", MAX_REST_HOURS), MessageColor::Red);
+                return;
+            }
+            Err(err) => {
+                self.add_message(&err, MessageColor::Red);
+                return;
+            }
+        };
+        let (energy, health) = (self.energy, self.health);
+        let fires = self.fire_levels.clone();
+        for _ in 0..hours * self.config.ticks_per_hour.max(1) {
+            self.tick();
+            if self.state == AppState::GameOver {
+                return;
+            }
+        }
+        self.mark_changed();
+        let spread = self
+            .fire_levels
+            .iter()
+            .filter(|(location, level)| fires.get(*location).is_some_and(|before| *level > before))
+            .count();
+        let mut summary = format!(
+            "You rest for {} hour{}, recovering {} energy.",
+            hours,
+            if hours == 1 { "" } else { "s" },
+            self.energy - energy,
+        );
+        if spread > 0 {
+            summary.push_str(&format!(" The fire spread in {} location{}.", spread, if spread == 1 { "" } else { "s" }));
+        }
+        if self.health < health {
+            summary.push_str(&format!(" The heat cost you {} health.", health - self.health));
+        }
+        self.add_message(&summary, MessageColor::Green);
+    }
+
+    // Fire creeps one level toward any neighbor burning at least two levels hotter,
+    // computed from the previous state so the result doesn't depend on map order
+    fn spread_fire(&mut self) {
+        let previous = self.fire_levels.clone();
+        let mut spread = Vec::new();
+        for (location, exits) in &self.map {
+            let own = previous.get(location).copied().unwrap_or(0);
+            let hottest = exits
+                .values()
+                .filter_map(|neighbor| previous.get(neighbor).copied())
+                .max()
+                .unwrap_or(0);
+            if hottest > own.saturating_add(1) {
+                self.fire_levels.insert(location.clone(), (own + 1).min(MAX_FIRE));
+                spread.push(location.clone());
+            }
+        }
+        if !spread.is_empty() {
+            spread.sort();
+            self.add_event(&format!("The fire spreads to {}.", spread.join(", ")), MessageColor::Red);
+        }
+    }
+
+    fn fire_level(&self) -> u8 {
+        self.fire_levels.get(&self.location).copied().unwrap_or(0)
+    }
+
+    fn report_fire(&mut self) {
+        let level = self.fire_level();
+        self.add_message(
+            &format!("Fire level at {}: {}/{}", self.location, level, MAX_FIRE),
+            fire_color(level),
+        );
+    }
+
+    // True for a moment after a command fails, while the input border is red
+    fn error_flash(&self) -> bool {
+        self.last_error_tick.is_some_and(|tick| self.ui_ticks <= tick + ERROR_FLASH_TICKS)
+    }
+
+    // Advances the clock UI effects run on, which doesn't stop with the game;
+    // true when an effect just ended and the screen needs redrawing
+    fn ui_tick(&mut self) -> bool {
+        let flashing = self.input_overflow || self.error_flash();
+        self.input_overflow = false;
+        self.ui_ticks += 1;
+        flashing && !self.error_flash()
+    }
+
+    // Ctrl-C always asks to quit, since raw mode keeps it from interrupting;
+    // Esc only does when no prompt or mode is waiting to be cancelled by it
+    fn is_quit_key(&self, key: KeyEvent) -> bool {
+        match key.code {
+            KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+            KeyCode::Esc => !self.composing && self.search.is_none() && self.pending_confirm.is_none(),
+            _ => false,
+        }
+    }
+
+    // Returns true when the game should exit, asking for confirmation first if
+    // there is unsaved progress
+    fn request_quit(&mut self) -> bool {
+        if self.dirty && !self.quit_armed {
+            self.quit_armed = true;
+            self.add_bold_message(
+                "Unsaved changes — quit again (Esc or Ctrl-C work too) to confirm, or 'save' first.",
+                MessageColor::Yellow,
+            );
+            return false;
+        }
+        true
+    }
+
+    // In-game (day, hour), starting at day 1 hour 0
+    fn game_time(&self) -> (u32, u32) {
+        let hours = self.tick_count / self.config.ticks_per_hour.max(1);
+        ((hours / 24 + 1) as u32, (hours % 24) as u32)
+    }
+
+    fn display_theme(&self) -> Theme {
+        if self.no_color {
+            Theme::plain()
+        } else {
+            self.theme.clone()
+        }
+    }
+
+    fn total_resources(&self) -> u32 {
+        self.inventory.values().sum()
+    }
+
+    fn repeat_last(&mut self) -> CommandResult {
+        let command = self.last_command.clone();
+        if command.trim().is_empty() {
+            self.add_message("Nothing to repeat.", MessageColor::Yellow);
+            return CommandResult::Continue;
+        }
+        self.add_dim_message(&command, MessageColor::White);
+        let repeating = std::mem::replace(&mut self.repeating, true);
+        let result = self.handle_command(&command);
+        self.repeating = repeating;
+        result
+    }
+
+    fn record_history(&mut self) {
+        self.history_index = None;
+        let command = self.last_command.trim();
+        if command.is_empty() || self.history.last().map(String::as_str) == Some(command) {
+            return;
+        }
+        self.history.push(command.to_string());
+        if self.history.len() > MAX_HISTORY {
+            let excess = self.history.len() - MAX_HISTORY;
+            self.history.drain(..excess);
+        }
+    }
+
+    fn save_history(&self) -> io::Result<()> {
+        let start = self.history.len().saturating_sub(MAX_HISTORY);
+        let mut contents = self.history[start..].join("\n");
+        contents.push('\n');
+        fs::write(HISTORY_FILE, contents)
+    }
+
+    fn history_up(&mut self) {
+        if self.history.is_empty() {
+            return;
+        }
+        let index = match self.history_index {
+            None => self.history.len() - 1,
+            Some(i) => i.saturating_sub(1),
+        };
+        self.history_index = Some(index);
+        self.set_input(self.history[index].clone());
+    }
+
+    fn history_down(&mut self) {
+        match self.history_index {
+            Some(i) if i + 1 < self.history.len() => {
+                self.history_index = Some(i + 1);
+                self.set_input(self.history[i + 1].clone());
+            }
+            Some(_) => {
+                // Moving past the newest entry restores an empty prompt
+                self.history_index = None;
+                self.take_input();
+            }
+            None => {}
+        }
+    }
+
+    fn get_autocomplete_suggestions(&self) -> Vec<String> {
+        // Match case-insensitively, the same way commands are executed
+        let input = self.input.to_lowercase();
+        if let Some((name, partial)) = input.split_once(' ') {
+            // Only the first argument is completed, as a whole input line
+            let partial = partial.trim_start();
+            if partial.contains(' ') {
+                return Vec::new();
+            }
+            return self
+                .argument_candidates(&self.resolve_alias(name))
+                .into_iter()
+                .filter(|candidate| candidate.starts_with(partial))
+                .map(|candidate| format!("{} {}", name, candidate))
+                .collect();
+        }
+        let mut suggestions = self.commands
+            .keys()
+            .filter(|cmd| cmd.starts_with(&input))
+            .cloned()
+            .collect::<Vec<_>>();
+        suggestions.sort();
+        // An alias suggests the command it expands to
+        let mut expansions = self.aliases
+            .iter()
+            .filter(|(short, _)| short.starts_with(&input))
+            .map(|(_, full)| full.clone())
+            .collect::<Vec<_>>();
+        expansions.sort();
+        for full in expansions {
+            if !suggestions.contains(&full) {
+                suggestions.push(full);
+            }
+        }
+        suggestions
+    }
+
+    // Valid first arguments for a command, sorted
+    fn argument_candidates(&self, command: &str) -> Vec<String> {
+        let mut candidates = match command {
+            "go" => self.map
+                .get(&self.location)
+                .map(|exits| exits.keys().cloned().collect())
+                .unwrap_or_default(),
+            "gather" => RESOURCES.iter().map(|resource| resource.to_string()).collect(),
+            "craft" => self.recipes.iter().map(|recipe| recipe.output.clone()).collect(),
+            "trade" => self.inventory.keys().filter(|item| RESOURCES.contains(&item.as_str())).cloned().collect(),
+            "build" => default_structures().into_iter().map(|structure| structure.name).collect(),
+            "drop" => self.inventory.keys().cloned().collect(),
+            "help" => self.commands.keys().cloned().collect(),
+            "theme" => THEMES.iter().map(|theme| theme.to_string()).collect(),
+            "cursor" => CURSOR_STYLES.iter().chain(&["blink"]).map(|style| style.to_string()).collect(),
+            _ => Vec::<String>::new(),
+        };
+        candidates.sort();
+        candidates
+    }
+
+    // The registered command nearest to `input`, if it's plausibly a typo
+    fn closest_command(&self, input: &str) -> Option<&String> {
+        self.commands
+            .keys()
+            .map(|cmd| (levenshtein(input, cmd), cmd))
+            .filter(|(distance, _)| *distance <= 2)
+            .min()
+            .map(|(_, cmd)| cmd)
+    }
+
+    fn resolve_alias(&self, name: &str) -> String {
+        self.aliases.get(name).cloned().unwrap_or_else(|| name.to_string())
+    }
+
+    fn complete_input(&mut self) {
+        let suggestions = self.get_autocomplete_suggestions();
+        match suggestions.as_slice() {
+            [] => self.add_message("No completion.", MessageColor::White),
+            [only] => self.set_input(only.clone()),
+            _ => {
+                // Alias expansions needn't share what was typed, so only the
+                // suggestions that do decide how far the input can grow
+                let typed = self.input.to_lowercase();
+                let matching = suggestions.iter().filter(|s| s.starts_with(&typed)).cloned().collect::<Vec<_>>();
+                let prefix = longest_common_prefix(&matching);
+                if prefix.len() > typed.len() {
+                    self.set_input(prefix);
+                }
+                self.add_message(&suggestions.join(", "), MessageColor::Cyan);
+            }
+        }
+    }
+
+    fn add_message(&mut self, content: &str, color: MessageColor) {
+        self.push_message(StoredMessage::new(content, color, self.tick_count));
+    }
+
+    // For the few messages that must stand out, like dying
+    fn add_bold_message(&mut self, content: &str, color: MessageColor) {
+        let message = StoredMessage {
+            bold: true,
+            ..StoredMessage::new(content, color, self.tick_count)
+        };
+        self.push_message(message);
+    }
+
+    // Faded out, for echoes of commands the player didn't type this time
+    fn add_dim_message(&mut self, content: &str, color: MessageColor) {
+        let message = StoredMessage {
+            dim: true,
+            ..StoredMessage::new(content, color, self.tick_count)
+        };
+        self.push_message(message);
+    }
+
+    // Drawn on a colored background, for alerts that must not be missed
+    fn add_message_bg(&mut self, content: &str, color: MessageColor, bg: MessageColor) {
+        let message = StoredMessage {
+            bg: Some(bg),
+            ..StoredMessage::new(content, color, self.tick_count)
+        };
+        self.push_message(message);
+    }
+
+    // Like add_message, but for things the game does on its own; they go to
+    // the events panel so command output can't scroll them away
+    fn add_event(&mut self, content: &str, color: MessageColor) {
+        self.push_event(StoredMessage::new(content, color, self.tick_count));
+    }
+
+    fn push_event(&mut self, event: StoredMessage) {
+        if self.events.len() >= MAX_EVENTS {
+            self.events.remove(0);
+        }
+        self.events.push(event);
+    }
+
+    fn push_message(&mut self, mut message: StoredMessage) {
+        if message.color == MessageColor::Red {
+            self.errors_logged += 1;
+        }
+        // Collapse a run of identical messages into one counted entry
+        if let Some(last) = self.messages.get_mut(self.message_index) {
+            if last.repeats(&message) {
+                last.count += 1;
+                last.tick = message.tick;
+                last.hidden = 0;
+                return;
+            }
+        }
+        if self.config.typewriter && !self.paused {
+            let pending = self.messages.iter().filter(|msg| msg.hidden > 0).count();
+            if pending < TYPEWRITER_MAX_PENDING {
+                message.hidden = strip_markup(&message.content).chars().count();
+            } else {
+                // Too much is arriving to type out; show it all rather than fall behind
+                self.reveal_messages();
+            }
+        }
+        if self.messages.len() >= self.max_messages {
+            // Use ring buffer behavior: the oldest entry sits just after the newest
+            self.message_index = (self.message_index + 1) % self.messages.len();
+            self.messages[self.message_index] = message;
+        } else {
+            self.messages.push(message);
+            self.message_index = self.messages.len() - 1;
+        }
+    }
+
+    fn advance_typewriter(&mut self) {
+        for msg in self.messages.iter_mut().filter(|msg| msg.hidden > 0) {
+            msg.hidden = msg.hidden.saturating_sub(TYPEWRITER_CHARS_PER_TICK);
+        }
+    }
+
+    fn reveal_messages(&mut self) {
+        for msg in &mut self.messages {
+            msg.hidden = 0;
+        }
+    }
+
+    fn toggle_typewriter(&mut self) {
+        self.config.typewriter = !self.config.typewriter;
+        if !self.config.typewriter {
+            self.reveal_messages();
+        }
+        let state = if self.config.typewriter { "on" } else { "off" };
+        self.add_message(&format!("Typewriter effect {}.", state), MessageColor::White);
+        self.save_config();
+    }
+
+    // Messages in logical order, oldest first
+    fn ordered_messages(&self) -> impl DoubleEndedIterator<Item = &StoredMessage> {
+        let split = if self.messages.len() >= self.max_messages {
+            (self.message_index + 1).min(self.messages.len())
+        } else {
+            0
+        };
+        let (newer, older) = self.messages.split_at(split);
+        older.iter().chain(newer.iter())
+    }
+
+    // Emphasis for a message, dimmed once it has aged so recent output stands out
+    fn message_modifiers(&self, msg: &StoredMessage) -> Modifier {
+        let mut modifiers = msg.modifiers();
+        if self.tick_count.saturating_sub(msg.tick) > STALE_MESSAGE_TICKS {
+            modifiers |= Modifier::DIM;
+        }
+        modifiers
+    }
+
+    fn format_message(&self, msg: &StoredMessage) -> String {
+        let repeats = if msg.count > 1 { format!(" (x{})", msg.count) } else { String::new() };
+        if self.config.timestamps {
+            let secs = msg.tick * self.config.tick_rate_ms / 1000;
+            format!("> [{:04}s] {}{}", secs, msg.content, repeats)
+        } else {
+            format!("> {}{}", msg.content, repeats)
+        }
+    }
+
+    // As many messages as fit in a panel of the given inner size once wrapped,
+    // ending `scroll_offset` entries before the newest, oldest first
+    fn fitting_messages(&self, width: usize, height: usize) -> Vec<&StoredMessage> {
+        let mut used = 0;
+        let mut window = Vec::new();
+        for msg in self.filtered_messages().rev().skip(self.scroll_offset) {
+            used += wrapped_line_count(&strip_markup(&self.format_message(msg)), width);
+            // Always show at least one message, even if it gets cut off
+            if used > height && !window.is_empty() {
+                break;
+            }
+            window.push(msg);
+        }
+        window.reverse();
+        window
+    }
+
+    // The log as shown, in order, after any color filter
+    fn filtered_messages(&self) -> impl DoubleEndedIterator<Item = &StoredMessage> {
+        self.ordered_messages()
+            .filter(|msg| self.filter.is_none_or(|color| msg.color == color))
+    }
+
+    fn max_scroll_offset(&self) -> usize {
+        self.filtered_messages().count().saturating_sub(self.page_height)
+    }
+
+    fn set_filter(&mut self, name: &str) {
+        if name == "off" {
+            self.filter = None;
+            self.add_message("Showing every message.", MessageColor::White);
+            return;
+        }
+        match MessageColor::by_name(name) {
+            Some(color) => {
+                self.filter = Some(color);
+                self.add_message(&format!("Showing only {} messages. Type 'filter off' to see all.", name), color);
+            }
+            None => self.add_message(
+                &format!("Unknown color. Valid colors: {}, or 'off'", MESSAGE_COLORS.join(", ")),
+                MessageColor::Red,
+            ),
+        }
+    }
+
+    fn scroll_up(&mut self) {
+        self.scroll_offset = (self.scroll_offset + self.page_height).min(self.max_scroll_offset());
+    }
+
+    fn scroll_down(&mut self) {
+        self.scroll_offset = self.scroll_offset.saturating_sub(self.page_height);
+    }
+
+    fn resize(&mut self, width: u16, height: u16) {
+        self.screen_size = (width, height);
+        let message_area = main_layout(Rect::new(0, 0, width, height), self.composing)[1];
+        self.page_height = (message_area.height.saturating_sub(2) as usize).max(1);
+        self.scroll_offset = self.scroll_offset.min(self.max_scroll_offset());
+    }
+
+    // Recomputes the page height after the layout itself changes
+    fn relayout(&mut self) {
+        let (width, height) = self.screen_size;
+        if width > 0 && height > 0 {
+            self.resize(width, height);
+        }
+    }
+
+    // Changes the ring buffer capacity, laying the log out oldest first again
+    // and dropping the oldest messages that no longer fit
+    fn set_max_messages(&mut self, max: usize) {
+        let max = max.max(MIN_MESSAGES);
+        let mut messages = self.ordered_messages().cloned().collect::<Vec<_>>();
+        messages.drain(..messages.len().saturating_sub(max));
+        self.message_index = messages.len().saturating_sub(1);
+        self.messages = messages;
+        self.max_messages = max;
+        self.scroll_offset = self.scroll_offset.min(self.max_scroll_offset());
+    }
+
+    fn set_log_size(&mut self, size: &str) {
+        if size.is_empty() {
+            let message = format!("The log keeps the last {} messages.", self.max_messages);
+            self.add_message(&message, MessageColor::Cyan);
+            return;
+        }
+        let size = match parse_amount(size) {
+            Ok(size) => size,
+            Err(err) => {
+                self.add_message(&err, MessageColor::Red);
+                return;
+            }
+        };
+        self.set_max_messages(size as usize);
+        self.config.max_messages = self.max_messages;
+        let message = format!("The log now keeps the last {} messages.", self.max_messages);
+        self.add_message(&message, MessageColor::Green);
+        self.save_config();
+    }
+
+    fn clear_messages(&mut self) {
+        self.messages.clear();
+        self.message_index = 0;
+        self.scroll_offset = 0;
+        self.add_message("Log cleared.", MessageColor::White);
+    }
+
+    // Runs each line of a script file as a command, skipping blank lines and
+    // '#' comments, and stops early if one of them quits
+    fn run_file(&mut self, path: &str) -> CommandResult {
+        if path.is_empty() {
+            self.add_message("Usage: run <file>", MessageColor::Red);
+            return CommandResult::Continue;
+        }
+        if self.run_depth >= MAX_RUN_DEPTH {
+            self.add_message(
+                &format!("Not running {}: scripts can only nest {} deep.", path, MAX_RUN_DEPTH),
+                MessageColor::Red,
+            );
+            return CommandResult::Continue;
+        }
+        let script = match fs::read_to_string(path) {
+            Ok(script) => script,
+            Err(err) => {
+                self.add_message(&format!("Failed to run {}: {}", path, err), MessageColor::Red);
+                return CommandResult::Continue;
+            }
+        };
+        // Restored rather than decremented, since a script's 'undo' or 'load'
+        // swaps in a whole new game partway through
+        let depth = self.run_depth;
+        self.run_depth = depth + 1;
+        let mut result = CommandResult::Continue;
+        for line in script.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.add_dim_message(line, MessageColor::White);
+            result = self.handle_command(line);
+            if result == CommandResult::Quit {
+                break;
+            }
+        }
+        self.run_depth = depth;
+        result
+    }
+
+    fn export_log(&mut self, path: &str) {
+        let path = if path.is_empty() { LOG_FILE } else { path };
+        let lines = self.ordered_messages()
+            .map(|msg| format!("[{:?}] {}", msg.color, msg.content))
+            .collect::<Vec<_>>();
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        match fs::write(path, contents) {
+            Ok(()) => self.add_message(
+                &format!("Exported {} lines to {}.", lines.len(), path),
+                MessageColor::Green,
+            ),
+            Err(err) => self.add_message(&format!("Failed to export log: {}", err), MessageColor::Red),
+        }
+    }
+
+    // Awards every achievement whose milestone has just been reached
+    fn check_achievements(&mut self) {
+        // Nothing is earned after death, even by commands typed on the game over screen
+        if self.state != AppState::Game {
+            return;
+        }
+        for achievement in achievement_list() {
+            if !self.achievements.contains(achievement.name) && (achievement.earned)(self) {
+                self.achievements.insert(achievement.name.to_string());
+                let event = StoredMessage {
+                    bold: true,
+                    ..StoredMessage::new(&format!("Achievement unlocked: {}", achievement.name), MessageColor::Yellow, self.tick_count)
+                };
+                self.push_event(event);
+                self.ring_bell();
+            }
+        }
+    }
+
+    fn show_achievements(&mut self) {
+        let list = achievement_list();
+        let earned = list
+            .iter()
+            .filter(|achievement| self.achievements.contains(achievement.name))
+            .map(|achievement| format!("{}: {}", achievement.name, achievement.description))
+            .collect::<Vec<_>>();
+        if earned.is_empty() {
+            self.add_message("No achievements yet.", MessageColor::White);
+            return;
+        }
+        self.add_message(&format!("Achievements ({}/{}):", earned.len(), list.len()), MessageColor::Cyan);
+        for line in earned {
+            self.add_message(&line, MessageColor::Yellow);
+        }
+    }
+
+    fn total_playtime(&self) -> u64 {
+        self.played_secs + self.playtime_since.elapsed().as_secs()
+    }
+
+    // Folds the time played so far into played_secs so a save records it
+    fn sync_playtime(&mut self) {
+        self.played_secs = self.total_playtime();
+        self.playtime_since = Instant::now();
+    }
+
+    fn show_time(&mut self) {
+        let (day, hour) = self.game_time();
+        let lines = [
+            format!("Played this session: {}", format_duration(self.start.elapsed().as_secs())),
+            format!("Played in total: {}", format_duration(self.total_playtime())),
+            format!("In-game time: day {}, {:02}:00", day, hour),
+        ];
+        for line in lines {
+            self.add_message(&line, MessageColor::White);
+        }
+    }
+
+    fn show_version(&mut self) {
+        let version = match option_env!("PYROBASE_GIT_HASH") {
+            Some(hash) => format!("Pyrobase {} ({}), save format v{}", env!("CARGO_PKG_VERSION"), hash, SAVE_VERSION),
+            None => format!("Pyrobase {}, save format v{}", env!("CARGO_PKG_VERSION"), SAVE_VERSION),
+        };
+        self.add_message(&version, MessageColor::Cyan);
+    }
+
+    fn show_stats(&mut self) {
+        let (day, hour) = self.game_time();
+        let lines = [
+            (format!("Commands entered: {}", self.commands_entered), MessageColor::White),
+            (format!("Time: tick {} (day {}, {:02}:00)", self.tick_count, day, hour), MessageColor::Yellow),
+            (format!("Messages logged: {}", self.messages.len()), MessageColor::White),
+            (format!("Resources gathered: {}", self.resources_gathered), MessageColor::Green),
+            (format!("Locations visited: {}", self.visited.len()), MessageColor::Blue),
+            (format!("Difficulty: {}", self.difficulty.name()), MessageColor::White),
+            (format!("Seed: {}", self.seed), MessageColor::White),
+        ];
+        self.add_message("Session stats:", MessageColor::Cyan);
+        for (line, color) in lines {
+            self.add_message(&line, color);
+        }
+    }
+
+    fn note(&mut self, words: &[String]) {
+        match words {
+            [] => self.add_message("Usage: note <text>", MessageColor::Red),
+            [word] if word.eq_ignore_ascii_case("clear") => {
+                self.notes.clear();
+                self.mark_changed();
+                self.add_message("Journal cleared.", MessageColor::White);
+            }
+            _ => {
+                self.notes.push(words.join(" "));
+                self.mark_changed();
+                self.add_message("Noted.", MessageColor::Green);
+            }
+        }
+    }
+
+    fn search_log(&mut self, term: &str) {
+        if term.is_empty() {
+            self.add_message("Usage: search <term>", MessageColor::Red);
+            return;
+        }
+        let matches = self
+            .ordered_messages()
+            .enumerate()
+            .filter(|(_, msg)| msg.content.to_lowercase().contains(term))
+            .map(|(i, msg)| format!("[{}] {}", i, msg.content))
+            .collect::<Vec<_>>();
+        if matches.is_empty() {
+            self.add_message(&format!("No matches for '{}'.", term), MessageColor::Yellow);
+            return;
+        }
+        let skipped = matches.len().saturating_sub(MAX_SEARCH_RESULTS);
+        for line in matches.into_iter().skip(skipped) {
+            self.add_message(&line, MessageColor::White);
+        }
+    }
+
+    fn show_notes(&mut self) {
+        if self.notes.is_empty() {
+            self.add_message("Your journal is empty.", MessageColor::White);
+            return;
+        }
+        // Composed entries can span lines, which are indented under the number
+        let lines = self.notes
+            .iter()
+            .enumerate()
+            .flat_map(|(i, note)| {
+                note.lines().enumerate().map(move |(line_number, line)| match line_number {
+                    0 => format!("{}. {}", i + 1, line),
+                    _ => format!("   {}", line),
+                })
+            })
+            .collect::<Vec<_>>();
+        self.add_message("Journal:", MessageColor::Cyan);
+        for line in lines {
+            self.add_message(&line, MessageColor::White);
+        }
+    }
+
+    fn ring_bell(&mut self) {
+        if self.config.bell {
+            self.bell_pending = true;
+        }
+    }
+
+    fn toggle_bell(&mut self) {
+        self.config.bell = !self.config.bell;
+        let state = if self.config.bell { "on" } else { "off" };
+        self.add_message(&format!("Bell {}.", state), MessageColor::White);
+        self.save_config();
+    }
+
+    // The most recent commands, numbered by their place in the whole history
+    fn show_command_history(&mut self, count: &str) {
+        let count = if count.is_empty() { Ok(DEFAULT_HISTORY_LISTING as u32) } else { parse_amount(count) };
+        let count = match count {
+            Ok(count) => count as usize,
+            Err(err) => {
+                self.add_message(&err, MessageColor::Red);
+                return;
+            }
+        };
+        let skip = self.history.len().saturating_sub(count);
+        let lines = self.history
+            .iter()
+            .enumerate()
+            .skip(skip)
+            .map(|(i, command)| format!("{:>4}  {}", i + 1, command))
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            self.add_message("No commands in history.", MessageColor::White);
+            return;
+        }
+        self.add_message(&format!("Last {} commands:", lines.len()), MessageColor::Cyan);
+        for line in lines {
+            self.add_message(&line, MessageColor::White);
+        }
+    }
+
+    fn toggle_timestamps(&mut self) {
+        self.config.timestamps = !self.config.timestamps;
+        let state = if self.config.timestamps { "on" } else { "off" };
+        self.add_message(&format!("Timestamps {}.", state), MessageColor::White);
+        self.save_config();
+    }
+
+    fn toggle_colors(&mut self) {
+        self.no_color = !self.no_color;
+        self.config.no_color = self.no_color;
+        let state = if self.no_color { "off" } else { "on" };
+        self.add_message(&format!("Colors {}.", state), MessageColor::White);
+        self.save_config();
+    }
+
+    fn save_config(&mut self) {
+        if let Err(err) = self.config.save() {
+            self.add_message(&format!("Failed to save the config: {}", err), MessageColor::Red);
+        }
+    }
+
+    fn setting_enabled(&self, setting: Setting) -> bool {
+        match setting {
+            Setting::Timestamps => self.config.timestamps,
+            Setting::Colors => !self.no_color,
+            Setting::Sound => self.config.bell,
+            Setting::Typewriter => self.config.typewriter,
+        }
+    }
+
+    fn toggle_setting(&mut self, setting: Setting) {
+        match setting {
+            Setting::Timestamps => self.toggle_timestamps(),
+            Setting::Colors => self.toggle_colors(),
+            Setting::Sound => self.toggle_bell(),
+            Setting::Typewriter => self.toggle_typewriter(),
+        }
+    }
+
+    fn open_settings(&mut self) {
+        self.settings_from = Some(self.state);
+        self.settings_selected = 0;
+        self.state = AppState::Settings;
+    }
+
+    fn close_settings(&mut self) {
+        self.state = self.settings_from.take().unwrap_or(AppState::Game);
+    }
+
+    fn settings_key(&mut self, key: KeyEvent) {
+        let count = SETTINGS.len() + 1;
+        match key.code {
+            KeyCode::Up => self.settings_selected = (self.settings_selected + count - 1) % count,
+            KeyCode::Down => self.settings_selected = (self.settings_selected + 1) % count,
+            KeyCode::Enter | KeyCode::Char(' ') => match SETTINGS.get(self.settings_selected) {
+                Some(setting) => self.toggle_setting(*setting),
+                None => self.close_settings(),
+            },
+            KeyCode::Esc => self.close_settings(),
+            _ => {}
+        }
+    }
+
+    fn toggle_minimap(&mut self) {
+        self.show_minimap = !self.show_minimap;
+        let state = if self.show_minimap { "on" } else { "off" };
+        self.add_message(&format!("Minimap {}.", state), MessageColor::White);
+    }
+
+    // Rooms drawn on the minimap: visited ones plus unexplored neighbours
+    fn reveal_map(&mut self) {
+        self.visited.extend(self.map.keys().cloned());
+        self.mark_changed();
+        self.add_message("Cheat: every location is now on the map.", MessageColor::Magenta);
+    }
+
+    fn known_locations(&self) -> HashSet<&String> {
+        let mut known = self.visited.iter().collect::<HashSet<_>>();
+        for location in &self.visited {
+            if let Some(exits) = self.map.get(location) {
+                known.extend(exits.values());
+            }
+        }
+        known
+    }
+
+    // ASCII grid of the explored map: '@' is you, '#' a visited room and
+    // '?' an unexplored one next to it, joined by '-' and '|' passages
+    fn minimap_lines(&self) -> Vec<String> {
+        let width = self.coordinates.values().map(|&(x, _)| x + 1).max().unwrap_or(0);
+        let height = self.coordinates.values().map(|&(_, y)| y + 1).max().unwrap_or(0);
+        let mut grid = vec![vec![' '; (width * 2 - 1).max(0) as usize]; (height * 2 - 1).max(0) as usize];
+        let known = self.known_locations();
+        for location in &known {
+            let Some(&(x, y)) = self.coordinates.get(*location) else {
+                continue;
+            };
+            let (column, row) = (x as usize * 2, y as usize * 2);
+            grid[row][column] = if **location == self.location {
+                '@'
+            } else if self.visited.contains(*location) {
+                '#'
+            } else {
+                '?'
+            };
+            // Only passages you have stood at one end of are drawn
+            let seen = |destination: &String| {
+                known.contains(destination)
+                    && (self.visited.contains(*location) || self.visited.contains(destination))
+            };
+            let exits = self.map.get(*location).into_iter().flatten();
+            for (direction, _) in exits.filter(|(_, destination)| seen(destination)) {
+                match direction.as_str() {
+                    "east" => grid[row][column + 1] = '-',
+                    "south" => grid[row + 1][column] = '|',
+                    _ => {}
+                }
+            }
+        }
+        grid.into_iter().map(|row| row.into_iter().collect()).collect()
+    }
+
+    fn set_theme(&mut self, args: &[String]) {
+        match args {
+            [keyword, name] if keyword == "palette" => match Palette::by_name(name) {
+                Some(palette) => {
+                    self.theme.palette = palette;
+                    self.add_message(&format!("Palette set to {}.", name), MessageColor::Green);
+                }
+                None => self.add_message(
+                    &format!("Unknown palette. Valid palettes: {}", PALETTES.join(", ")),
+                    MessageColor::Red,
+                ),
+            },
+            [name] => match Theme::by_name(name) {
+                Some(theme) => {
+                    // The palette is independent of the theme, so keep it
+                    self.theme = Theme { palette: self.theme.palette, ..theme };
+                    self.add_message(&format!("Theme set to {}.", name), MessageColor::Green);
+                }
+                None => self.add_message(
+                    &format!("Unknown theme. Valid themes: {}", THEMES.join(", ")),
+                    MessageColor::Red,
+                ),
+            },
+            _ => self.add_message("Usage: theme <name> or theme palette <name>", MessageColor::Red),
+        }
+    }
+
+    fn set_cursor(&mut self, args: &[String]) {
+        match args {
+            [] => {
+                let blink = if self.config.cursor_blink { "blinking" } else { "steady" };
+                let message = format!("Cursor: {}, {}.", self.config.cursor.name(), blink);
+                self.add_message(&message, MessageColor::Cyan);
+                return;
+            }
+            [keyword, state] if keyword == "blink" && (state == "on" || state == "off") => {
+                self.config.cursor_blink = state == "on";
+                self.add_message(&format!("Cursor blinking turned {}.", state), MessageColor::Green);
+            }
+            [name] => match CursorStyle::by_name(name) {
+                Some(style) => {
+                    self.config.cursor = style;
+                    self.add_message(&format!("Cursor set to {}.", name), MessageColor::Green);
+                }
+                None => {
+                    let message = format!("Unknown cursor style. Valid styles: {}", CURSOR_STYLES.join(", "));
+                    self.add_message(&message, MessageColor::Red);
+                    return;
+                }
+            },
+            _ => {
+                self.add_message("Usage: cursor <style> or cursor blink <on|off>", MessageColor::Red);
+                return;
+            }
+        }
+        self.save_config();
+    }
+
+    // The input cursor as configured, drawn after what has been typed
+    fn cursor_span(&self, theme: &Theme) -> Span<'static> {
+        let style = if self.config.cursor_blink { theme.cursor() } else { theme.text() };
+        Span::styled(self.config.cursor.symbol(), style)
+    }
+
+    // Every name the dispatcher accepts, aliases included, without descriptions
+    fn list_commands(&mut self) {
+        let mut names = self.commands.keys().chain(self.aliases.keys()).cloned().collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+        let width = names.iter().map(String::len).max().unwrap_or(0) + 2;
+        let rows = names
+            .chunks(COMMAND_COLUMNS)
+            .map(|row| row.iter().map(|name| format!("{:<width$}", name, width = width)).collect::<String>())
+            .collect::<Vec<_>>();
+        self.add_message(&format!("{} commands and aliases:", names.len()), MessageColor::Cyan);
+        for row in rows {
+            self.add_message(row.trim_end(), MessageColor::White);
+        }
+    }
+
+    fn show_help(&mut self, command: &str) {
+        if !command.is_empty() {
+            match self.help.get(command).cloned() {
+                Some((_, long)) => self.add_message(&format!("{}: {}", command, long), MessageColor::Cyan),
+                None => self.add_message(&format!("No such command '{}'.", command), MessageColor::Red),
+            }
+            return;
+        }
+        let mut names = self.commands.keys().collect::<Vec<_>>();
+        names.sort();
+        let lines = names
+            .into_iter()
+            .filter_map(|name| self.help.get(name).map(|(short, _)| format!("{} - {}", name, short)))
+            .collect::<Vec<_>>();
+        self.add_message("Available commands (type 'help <command>' for details):", MessageColor::Cyan);
+        for line in lines {
+            self.add_message(&line, MessageColor::Cyan);
+        }
+    }
+
+    fn gather(&mut self, resource: &str) {
+        if !RESOURCES.contains(&resource) {
+            self.add_message(
+                &format!("Unknown resource. Valid resources: {}", RESOURCES.join(", ")),
+                MessageColor::Red,
+            );
+            return;
+        }
+        let range = self.difficulty.gather_range(resource);
+        if self.total_weight() + item_weight(resource) * range.1 > MAX_WEIGHT {
+            self.add_message("Too heavy — drop something first.", MessageColor::Red);
+            return;
+        }
+        if !self.spend_energy(self.difficulty.energy_cost(GATHER_ENERGY)) {
+            return;
+        }
+        let amount = self.roll(range);
+        *self.inventory.entry(resource.to_string()).or_insert(0) += amount;
+        self.resources_gathered += u64::from(amount);
+        self.mark_changed();
+        self.add_message(&format!("Gathered {} {}", amount, resource), MessageColor::Green);
+    }
+
+    fn total_weight(&self) -> u32 {
+        self.inventory.iter().map(|(item, amount)| item_weight(item) * amount).sum()
+    }
+
+    // Drops some of an item, or all of it when no amount is given
+    fn drop_item(&mut self, args: &[String]) {
+        if let [all] = args {
+            if all == "all" {
+                self.drop_everything();
+                return;
+            }
+        }
+        let (item, amount) = match args {
+            [item] => (item, None),
+            [item, amount] => match parse_amount(amount) {
+                Ok(amount) => (item, Some(amount)),
+                Err(err) => {
+                    self.add_message(&err, MessageColor::Red);
+                    return;
+                }
+            },
+            _ => {
+                self.add_message("Usage: drop <resource> [amount]", MessageColor::Red);
+                return;
+            }
+        };
+        let Some(held) = self.inventory.get_mut(item.as_str()) else {
+            self.add_message(&format!("You don't have any {}.", item), MessageColor::Red);
+            return;
+        };
+        let had = *held;
+        let dropped = amount.unwrap_or(had).min(had);
+        *held -= dropped;
+        if *held == 0 {
+            self.inventory.remove(item.as_str());
+        }
+        self.mark_changed();
+        if amount.is_some_and(|amount| amount > had) {
+            self.add_message(&format!("You only had {} {}, so you dropped all of it.", had, item), MessageColor::Yellow);
+        } else {
+            self.add_message(&format!("Dropped {} {}.", dropped, item), MessageColor::White);
+        }
+    }
+
+    fn drop_everything(&mut self) {
+        if self.inventory.is_empty() {
+            self.add_message("You aren't carrying anything.", MessageColor::Yellow);
+            return;
+        }
+        self.inventory.clear();
+        self.mark_changed();
+        self.add_message("You dropped everything you were carrying.", MessageColor::White);
+    }
+
+    // Commands that throw away progress wait for a y/n answer first
+    fn needs_confirmation(&self, cmd: &str) -> bool {
+        let (name, args) = parse_command(cmd);
+        match self.resolve_alias(&name).as_str() {
+            "clear" | "restart" => true,
+            "drop" => matches!(args.as_slice(), [arg] if arg.eq_ignore_ascii_case("all")),
+            _ => false,
+        }
+    }
+
+    // 'y' runs the pending command; any other key cancels it
+    fn answer_confirm(&mut self, key: KeyEvent) {
+        let Some(cmd) = self.pending_confirm.take() else {
+            return;
+        };
+        if matches!(key.code, KeyCode::Char('y' | 'Y')) {
+            self.confirmed = true;
+            self.handle_command(&cmd);
+        } else {
+            self.add_message(&format!("Cancelled '{}'.", cmd), MessageColor::White);
+        }
+    }
+
+    fn show_inventory(&mut self) {
+        if self.inventory.is_empty() {
+            self.add_message("Inventory empty.", MessageColor::White);
+            return;
+        }
+        let mut items = self.inventory
+            .iter()
+            .map(|(name, amount)| format!("{}: {}", name, amount))
+            .collect::<Vec<_>>();
+        items.sort();
+        self.add_message("Inventory:", MessageColor::Cyan);
+        for item in items {
+            self.add_message(&item, MessageColor::White);
+        }
+    }
+
+    fn craft(&mut self, item: &str) {
+        let Some(recipe) = self.recipes.iter().find(|recipe| recipe.output == item).cloned() else {
+            let outputs = self.recipes.iter().map(|recipe| recipe.output.as_str()).collect::<Vec<_>>();
+            self.add_message(
+                &format!("Unknown recipe. Craftable items: {}", outputs.join(", ")),
+                MessageColor::Red,
+            );
+            return;
+        };
+        if !self.take_inputs(&recipe.inputs) {
+            return;
+        }
+        *self.inventory.entry(recipe.output.clone()).or_insert(0) += 1;
+        self.mark_changed();
+        self.add_message(&format!("Crafted 1 {}", recipe.output), MessageColor::Green);
+    }
+
+    // Uses up the given resources, or reports what's short and takes nothing
+    fn take_inputs(&mut self, inputs: &HashMap<String, u32>) -> bool {
+        let mut missing = inputs
+            .iter()
+            .filter_map(|(name, needed)| {
+                let held = self.inventory.get(name).copied().unwrap_or(0);
+                (held < *needed).then(|| format!("{} {}", needed - held, name))
+            })
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            missing.sort();
+            self.add_message(&format!("Missing: {}", missing.join(", ")), MessageColor::Red);
+            return false;
+        }
+        for (name, needed) in inputs {
+            if let Some(held) = self.inventory.get_mut(name) {
+                *held -= needed;
+                if *held == 0 {
+                    self.inventory.remove(name);
+                }
+            }
+        }
+        true
+    }
+
+    fn build(&mut self, name: &str) {
+        let structures = default_structures();
+        let Some(structure) = structures.iter().find(|structure| structure.name == name) else {
+            let names = structures.iter().map(|structure| structure.name.as_str()).collect::<Vec<_>>();
+            self.add_message(&format!("Unknown structure. Buildable: {}", names.join(", ")), MessageColor::Red);
+            return;
+        };
+        if self.structures.contains(name) {
+            self.add_message(&format!("The {} is already built.", name), MessageColor::Yellow);
+            return;
+        }
+        if self.constructions.iter().any(|construction| construction.name == name) {
+            self.add_message(&format!("The {} is already under construction.", name), MessageColor::Yellow);
+            return;
+        }
+        if let Some(required) = structure.requires.as_ref().filter(|required| !self.structures.contains(*required)) {
+            self.add_message(&format!("The {} needs a finished {} first.", name, required), MessageColor::Red);
+            return;
+        }
+        if !self.take_inputs(&structure.inputs) {
+            return;
+        }
+        let ticks = structure.hours * self.config.ticks_per_hour.max(1);
+        self.constructions.push(Construction {
+            name: name.to_string(),
+            ticks_remaining: ticks,
+            total_ticks: ticks,
+        });
+        self.mark_changed();
+        self.add_message(
+            &format!("Construction of the {} has begun; it will take {} hours.", name, structure.hours),
+            MessageColor::Green,
+        );
+    }
+
+    fn advance_constructions(&mut self) {
+        for construction in &mut self.constructions {
+            construction.ticks_remaining = construction.ticks_remaining.saturating_sub(1);
+        }
+        let (finished, ongoing) = std::mem::take(&mut self.constructions)
+            .into_iter()
+            .partition::<Vec<_>, _>(|construction| construction.ticks_remaining == 0);
+        self.constructions = ongoing;
+        for construction in finished {
+            self.add_event(&format!("The {} is complete.", construction.name), MessageColor::Green);
+            self.structures.insert(construction.name);
+        }
+    }
+
+    fn show_construction(&mut self) {
+        if self.constructions.is_empty() && self.structures.is_empty() {
+            self.add_message("Nothing has been built yet. Try 'build <structure>'.", MessageColor::White);
+            return;
+        }
+        let lines = self.constructions
+            .iter()
+            .map(|construction| {
+                let done = construction.total_ticks - construction.ticks_remaining;
+                let filled = (done * CONSTRUCTION_BAR_WIDTH as u64 / construction.total_ticks.max(1)) as usize;
+                format!(
+                    "{} [{}{}] {}%",
+                    construction.name,
+                    "#".repeat(filled),
+                    "-".repeat(CONSTRUCTION_BAR_WIDTH - filled),
+                    done * 100 / construction.total_ticks.max(1),
+                )
+            })
+            .collect::<Vec<_>>();
+        if !lines.is_empty() {
+            self.add_message("Under construction:", MessageColor::Cyan);
+            for line in lines {
+                self.add_message(&line, MessageColor::Yellow);
+            }
+        }
+        if !self.structures.is_empty() {
+            let mut built = self.structures.iter().cloned().collect::<Vec<_>>();
+            built.sort();
+            self.add_message(&format!("Built: {}", built.join(", ")), MessageColor::Green);
+        }
+    }
+
+    fn trade(&mut self, args: &[String]) {
+        let [give, amount, keyword, get] = args else {
+            self.add_message("Usage: trade <resource> <amount> for <resource>", MessageColor::Red);
+            return;
+        };
+        if keyword != "for" {
+            self.add_message("Usage: trade <resource> <amount> for <resource>", MessageColor::Red);
+            return;
+        }
+        let amount = match parse_amount(amount) {
+            Ok(amount) => amount,
+            Err(err) => {
+                self.add_message(&err, MessageColor::Red);
+                return;
+            }
+        };
+        if let Some(unknown) = [give, get].into_iter().find(|name| !RESOURCES.contains(&name.as_str())) {
+            self.add_message(
+                &format!("Unknown resource '{}'. Valid resources: {}", unknown, RESOURCES.join(", ")),
+                MessageColor::Red,
+            );
+            return;
+        }
+        let Some(&(_, lot, _, rate)) = TRADES.iter().find(|(from, _, to, _)| from == give && to == get) else {
+            self.add_message(
+                &format!("Nobody trades {} for {}. Type 'trades' to see what's on offer.", give, get),
+                MessageColor::Red,
+            );
+            return;
+        };
+        if amount % lot != 0 {
+            self.add_message(&format!("{} is traded in lots of {}.", give, lot), MessageColor::Red);
+            return;
+        }
+        let held = self.inventory.get(give.as_str()).copied().unwrap_or(0);
+        if held < amount {
+            self.add_message(&format!("You only have {} {}.", held, give), MessageColor::Red);
+            return;
+        }
+        let received = amount / lot * rate;
+        if self.total_weight() - item_weight(give) * amount + item_weight(get) * received > MAX_WEIGHT {
+            self.add_message("Too heavy — drop something first.", MessageColor::Red);
+            return;
+        }
+        let inputs = HashMap::from([(give.clone(), amount)]);
+        self.take_inputs(&inputs);
+        *self.inventory.entry(get.clone()).or_insert(0) += received;
+        self.mark_changed();
+        self.add_message(&format!("Traded {} {} for {} {}.", amount, give, received, get), MessageColor::Green);
+    }
+
+    fn show_trades(&mut self) {
+        self.add_message("Trades:", MessageColor::Cyan);
+        for (give, lot, get, rate) in TRADES {
+            self.add_message(&format!("{} {} -> {} {}", lot, give, rate, get), MessageColor::White);
+        }
+    }
+
+    fn show_recipes(&mut self) {
+        let lines = self.recipes
+            .iter()
+            .map(|recipe| {
+                let mut inputs = recipe.inputs
+                    .iter()
+                    .map(|(name, amount)| format!("{} {}", amount, name))
+                    .collect::<Vec<_>>();
+                inputs.sort();
+                format!("{}: {}", recipe.output, inputs.join(", "))
+            })
+            .collect::<Vec<_>>();
+        self.add_message("Recipes:", MessageColor::Cyan);
+        for line in lines {
+            self.add_message(&line, MessageColor::White);
+        }
+    }
+
+    fn look(&mut self) {
+        let description = self.descriptions
+            .get(&self.location)
+            .cloned()
+            .unwrap_or_else(|| "You see scorched ruins.".to_string());
+        self.add_message(&format!("{}: {}", self.location, description), MessageColor::Cyan);
+        self.show_exits();
+    }
+
+    fn show_exits(&mut self) {
+        let exits = self.map.get(&self.location);
+        let exits = DIRECTIONS
+            .iter()
+            .filter(|direction| exits.is_some_and(|exits| exits.contains_key(**direction)))
+            .copied()
+            .collect::<Vec<_>>();
+        if exits.is_empty() {
+            self.add_message("This is a dead end.", MessageColor::Gray);
+        } else {
+            self.add_message(&format!("Exits: {}.", exits.join(", ")), MessageColor::Gray);
+        }
+    }
+
+    fn go(&mut self, direction: &str) {
+        if !DIRECTIONS.contains(&direction) {
+            self.add_message(
+                &format!("Unknown direction. Valid directions: {}", DIRECTIONS.join(", ")),
+                MessageColor::Red,
+            );
+            return;
+        }
+        let destination = self.map
+            .get(&self.location)
+            .and_then(|exits| exits.get(direction))
+            .cloned();
+        match destination {
+            Some(_) if !self.spend_energy(self.difficulty.energy_cost(MOVE_ENERGY)) => {}
+            Some(destination) => {
+                self.visited.insert(destination.clone());
+                self.today.visited.insert(destination.clone());
+                self.location = destination;
+                self.mark_changed();
+                self.look();
+                if self.fire_level() >= HIGH_FIRE {
+                    self.add_message("The flames here scorch you!", MessageColor::Red);
+                    self.damage(FIRE_ENTRY_DAMAGE);
+                }
+            }
+            None => self.add_message("You can't go that way.", MessageColor::Red),
+        }
+    }
+
+    fn alias(&mut self, args: &[String]) {
+        match args {
+            [] => {
+                let mut aliases = self.aliases
+                    .iter()
+                    .map(|(short, full)| format!("{} -> {}", short, full))
+                    .collect::<Vec<_>>();
+                aliases.sort();
+                self.add_message("Aliases:", MessageColor::Cyan);
+                for alias in aliases {
+                    self.add_message(&alias, MessageColor::White);
+                }
+            }
+            [short, full] => {
+                if self.commands.contains_key(short) || SHORTHANDS.iter().any(|(name, _)| name == short) {
+                    self.add_message(&format!("'{}' is already a command.", short), MessageColor::Red);
+                } else if !self.commands.contains_key(full) {
+                    self.add_message(&format!("No such command '{}'.", full), MessageColor::Red);
+                } else {
+                    self.aliases.insert(short.to_string(), full.to_string());
+                    self.mark_changed();
+                    self.add_message(&format!("Alias '{}' now runs '{}'.", short, full), MessageColor::Green);
+                }
+            }
+            _ => self.add_message("Usage: alias <short> <command>", MessageColor::Red),
+        }
+    }
+
+    // The slot named by a save or load argument, slot 1 when none is given
+    fn parse_slot(&mut self, arg: &str) -> Option<u32> {
+        if arg.is_empty() {
+            return Some(1);
+        }
+        match parse_amount(arg) {
+            Ok(slot) if slot <= SAVE_SLOTS => Some(slot),
+            Ok(_) => {
+                self.add_message(&format!("Save slots run from 1 to {}.", SAVE_SLOTS), MessageColor::Red);
+                None
+            }
+            Err(err) => {
+                self.add_message(&err, MessageColor::Red);
+                None
+            }
+        }
+    }
+
+    fn save_game(&mut self, arg: &str) {
+        let Some(slot) = self.parse_slot(arg) else {
+            return;
+        };
+        self.sync_playtime();
+        let result = serde_json::to_string_pretty(self)
+            .map_err(io::Error::from)
+            .and_then(|json| fs::write(save_path(slot), json));
+        match result {
+            Ok(()) => {
+                self.dirty = false;
+                self.add_message(&format!("Game saved to slot {}.", slot), MessageColor::Green);
+            }
+            Err(err) => self.add_message(&format!("Failed to save game: {}", err), MessageColor::Red),
+        }
+    }
+
+    // Runs one command line against the game, shared by the interactive loop and scripts
+    fn handle_command(&mut self, cmd: &str) -> CommandResult {
+        if self.state == AppState::GameOver {
+            // Only restarting or quitting is possible once dead
+            let (name, _) = parse_command(cmd);
+            match name.as_str() {
+                "q" | "quit" => return CommandResult::Quit,
+                "restart" => self.restart(),
+                _ => {}
+            }
+            return CommandResult::Continue;
+        }
+        if let Some(pending) = &self.pending_confirm {
+            let message = format!("Answer y or n for '{}' first.", pending);
+            self.add_message(&message, MessageColor::Red);
+            return CommandResult::Continue;
+        }
+        let confirmed = std::mem::take(&mut self.confirmed);
+        if !confirmed && self.needs_confirmation(cmd) {
+            // Nobody is there to answer, and the next lines would be taken as the answer
+            if self.run_depth > 0 {
+                self.add_message(
+                    &format!("Skipping '{}': scripts can't run commands that ask for confirmation.", cmd),
+                    MessageColor::Red,
+                );
+                return CommandResult::Continue;
+            }
+            self.pending_confirm = Some(cmd.to_string());
+            self.add_message(&format!("'{}' can't be taken back. Are you sure? (y/n)", cmd), MessageColor::Yellow);
+            return CommandResult::Continue;
+        }
+        let (name, args) = parse_command(cmd);
+        let name = self.resolve_alias(&name);
+        let name = SHORTHANDS
+            .iter()
+            .find(|(short, _)| *short == name)
+            .map_or(name, |(_, full)| full.to_string());
+        // The repeated command stands in for 'repeat' itself, so repeating
+        // twice runs the same command again rather than 'repeat'
+        if name != "repeat" {
+            self.last_command = cmd.to_string();
+            // Up and Down only recall what was actually typed
+            if self.run_depth == 0 && !self.repeating {
+                self.record_history();
+            }
+            if !name.is_empty() {
+                self.commands_entered += 1;
+            }
+        }
+        self.scroll_offset = 0;
+        if name != "quit" {
+            self.quit_armed = false;
+        }
+        // Serializing the whole game is too costly to do for every command
+        let snapshot = if UNDOABLE_COMMANDS.contains(&name.as_str()) {
+            serde_json::to_string(&*self).ok()
+        } else {
+            None
+        };
+        self.changed = false;
+        let errors_logged = self.errors_logged;
+        match self.commands.get(&name).cloned() {
+            Some(handler) => {
+                if handler(self, &args) == CommandResult::Quit {
+                    return CommandResult::Quit;
+                }
+            }
+            None if name.is_empty() => {}
+            // Left out of the command list and help on purpose
+            None if name == "reveal" && self.cheats => {
+                self.reveal_map();
+            }
+            None => {
+                self.add_message("Unknown command. Type 'help' for commands.", MessageColor::Red);
+                if let Some(suggestion) = self.closest_command(&name).cloned() {
+                    self.add_message(&format!("Did you mean '{}'?", suggestion), MessageColor::Yellow);
+                }
+            }
+        }
+        self.check_achievements();
+        if self.errors_logged > errors_logged {
+            self.last_error_tick = Some(self.ui_ticks);
+        }
+        if confirmed {
+            // The prompt promised this can't be taken back, so nothing before it can be either
+            self.undo_stack.clear();
+        } else if let Some(snapshot) = snapshot.filter(|_| self.changed) {
+            self.undo_stack.push(snapshot);
+            if self.undo_stack.len() > MAX_UNDO {
+                self.undo_stack.remove(0);
+            }
+        }
+        CommandResult::Continue
+    }
+
+    // Quietly writes the game to the autosave file; only failures are logged
+    fn autosave(&mut self) {
+        self.sync_playtime();
+        let result = serde_json::to_string_pretty(self)
+            .map_err(io::Error::from)
+            .and_then(|json| fs::write(AUTOSAVE_FILE, json));
+        match result {
+            Ok(()) => self.autosaved_at = Some(self.tick_count),
+            Err(err) => self.add_message(&format!("Autosave failed: {}", err), MessageColor::Red),
+        }
+    }
+
+    fn show_autosave_notice(&self) -> bool {
+        self.autosaved_at
+            .is_some_and(|tick| self.tick_count < tick + AUTOSAVE_NOTICE_TICKS)
+    }
+
+    fn show_saves(&mut self) {
+        self.add_message("Save slots:", MessageColor::Cyan);
+        for slot in 1..=SAVE_SLOTS {
+            let line = match fs::metadata(save_path(slot)).and_then(|meta| meta.modified()) {
+                Ok(modified) => {
+                    let modified = chrono::DateTime::<chrono::Local>::from(modified);
+                    format!("{}: saved {}", slot, modified.format("%Y-%m-%d %H:%M"))
+                }
+                Err(_) => format!("{}: empty", slot),
+            };
+            self.add_message(&line, MessageColor::White);
+        }
+    }
+
+    fn load_game(&mut self, arg: &str) {
+        let Some(slot) = self.parse_slot(arg) else {
+            return;
+        };
+        let mut read = fs::read_to_string(save_path(slot));
+        if slot == 1 && read.as_ref().is_err_and(|err| err.kind() == io::ErrorKind::NotFound) {
+            read = fs::read_to_string(LEGACY_SAVE_FILE);
+        }
+        let json = match read {
+            Ok(json) => json,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                self.add_message(&format!("Slot {} is empty.", slot), MessageColor::Yellow);
+                return;
+            }
+            Err(err) => {
+                self.add_message(&format!("Failed to load game: {}", err), MessageColor::Red);
+                return;
+            }
+        };
+        let loaded = serde_json::from_str::<serde_json::Value>(&json)
+            .map_err(|err| err.to_string())
+            .and_then(migrate_save);
+        match loaded {
+            Ok((mut loaded, from)) => {
+                let repairs = loaded.validate();
+                // Replace the running state wholesale so no field is left stale;
+                // undo never reaches back into the game that was replaced
+                self.replace_with(loaded);
+                self.undo_stack.clear();
+                if !repairs.is_empty() {
+                    self.add_message(&format!("Repaired the save: {}.", repairs.join(", ")), MessageColor::Yellow);
+                }
+                if from < SAVE_VERSION {
+                    self.add_message(&format!("Migrated save from v{} to v{}.", from, SAVE_VERSION), MessageColor::Yellow);
+                }
+                self.add_message(&format!("Game loaded from slot {}.", slot), MessageColor::Green);
+            }
+            Err(err) => self.add_message(&format!("Failed to load game: {}", err), MessageColor::Red),
+        }
+    }
+}
+
+fn save_path(slot: u32) -> String {
+    format!("pyrobase_save_{}.json", slot)
+}
+
+// The slot written to most recently, if any has a save
+fn latest_save_slot() -> Option<u32> {
+    let modified = |path: &str| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    (1..=SAVE_SLOTS)
+        .filter_map(|slot| {
+            let legacy = || if slot == 1 { modified(LEGACY_SAVE_FILE) } else { None };
+            Some((modified(&save_path(slot)).or_else(legacy)?, slot))
+        })
+        .max()
+        .map(|(_, slot)| slot)
+}
+
+// Brings a save written by an older version up to date, returning the game
+// along with the version it was saved as. Fields a save predates are filled
+// from a new game instead of failing the whole load
+fn migrate_save(mut save: serde_json::Value) -> Result<(App, u32), String> {
+    let from = match save.get("version") {
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or("invalid save version")?,
+        None => 1,
+    };
+    if from > SAVE_VERSION {
+        return Err(format!("save is from a newer version (v{})", from));
+    }
+    let fields = save.as_object_mut().ok_or("save is not a JSON object")?;
+    if from < SAVE_VERSION {
+        let defaults = serde_json::to_value(App::new()).map_err(|err| err.to_string())?;
+        for (key, value) in defaults.as_object().into_iter().flatten() {
+            fields.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+        fields.insert("version".to_string(), SAVE_VERSION.into());
+    }
+    let app = serde_json::from_value(save).map_err(|err| err.to_string())?;
+    Ok((app, from))
+}
+
+// A missing or unreadable history file just means starting with no history
+fn load_history() -> Vec<String> {
+    let contents = fs::read_to_string(HISTORY_FILE).unwrap_or_default();
+    let history = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let start = history.len().saturating_sub(MAX_HISTORY);
+    history[start..].to_vec()
+}
+
+// Splits input into a lowercased command name and its arguments, where
+// double quotes group several words into one argument
+fn parse_command(input: &str) -> (String, Vec<String>) {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut in_quotes = false;
+    for c in input.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                in_token = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut current));
+                    in_token = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_token = true;
+            }
+        }
+    }
+    if in_token {
+        tokens.push(current);
+    }
+    let mut tokens = tokens.into_iter();
+    let name = tokens.next().unwrap_or_default().to_lowercase();
+    (name, tokens.collect())
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// Formats a number of seconds as HH:MM:SS
+fn format_duration(secs: u64) -> String {
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+// Start row and length of the message scrollbar thumb within a track of
+// `track` rows, or None when every message is already on screen
+fn scrollbar_thumb(total: usize, visible: usize, offset: usize, track: usize) -> Option<(usize, usize)> {
+    if visible >= total || track == 0 {
+        return None;
+    }
+    let len = (track * visible / total).clamp(1, track);
+    let start = (track - len) * offset.min(total - visible) / (total - visible);
+    Some((start, len))
+}
+
+// Splits `{color:text}` markup into runs of text and the color they are drawn
+// in; anything that isn't well-formed markup with a known color stays literal
+fn markup_segments(text: &str) -> Vec<(Option<MessageColor>, String)> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('{') {
+        literal.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let markup = after.split_once('}').and_then(|(inner, tail)| {
+            let (name, content) = inner.split_once(':')?;
+            Some((MessageColor::by_name(name)?, content, tail))
+        });
+        match markup {
+            Some((color, content, tail)) => {
+                if !literal.is_empty() {
+                    segments.push((None, std::mem::take(&mut literal)));
+                }
+                segments.push((Some(color), content.to_string()));
+                rest = tail;
+            }
+            None => {
+                literal.push('{');
+                rest = after;
+            }
+        }
+    }
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        segments.push((None, literal));
+    }
+    segments
+}
+
+fn parse_markup(text: &str, base: Style, theme: &Theme) -> Vec<Span<'static>> {
+    markup_segments(text)
+        .into_iter()
+        .map(|(color, content)| match color {
+            Some(color) => Span::styled(content, base.fg(theme.message_color(color))),
+            None => Span::styled(content, base),
+        })
+        .collect()
+}
+
+// Cuts `count` characters off the end of a line, for the part of a message
+// the typewriter has yet to reach
+fn drop_trailing_chars(mut spans: Vec<Span<'static>>, mut count: usize) -> Vec<Span<'static>> {
+    while count > 0 {
+        let Some(last) = spans.last_mut() else {
+            break;
+        };
+        let len = last.content.chars().count();
+        if len > count {
+            let kept: String = last.content.chars().take(len - count).collect();
+            last.content = kept.into();
+            break;
+        }
+        count -= len;
+        spans.pop();
+    }
+    spans
+}
+
+// The text markup leaves on screen, for measuring wrapped lines
+fn strip_markup(text: &str) -> String {
+    markup_segments(text).into_iter().map(|(_, content)| content).collect()
+}
+
+// Estimates how many rows `text` takes when word-wrapped to `width` columns
+fn wrapped_line_count(text: &str, width: usize) -> usize {
+    if width == 0 {
+        return 1;
+    }
+    let mut lines = 1;
+    let mut line_len = 0;
+    for word in text.split_whitespace() {
+        let len = word.chars().count();
+        if line_len > 0 && line_len + 1 + len <= width {
+            line_len += 1 + len;
+            continue;
+        }
+        if line_len > 0 {
+            lines += 1;
+        }
+        // Words longer than the panel are broken across rows
+        lines += (len - 1) / width;
+        line_len = (len - 1) % width + 1;
+    }
+    lines
+}
+
+fn longest_common_prefix(words: &[String]) -> String {
+    let Some(first) = words.first() else {
+        return String::new();
+    };
+    let mut prefix = first.as_str();
+    for word in &words[1..] {
+        while !word.starts_with(prefix) {
+            let last_len = prefix.chars().last().map_or(0, char::len_utf8);
+            prefix = &prefix[..prefix.len() - last_len];
+        }
+    }
+    prefix.to_string()
+}
+
+// Raw mode delivers a pasted newline as Ctrl-J or a bare '\n', so treat both as Enter
+fn normalize_paste(event: Event) -> Event {
+    match event {
+        Event::Key(key) => {
+            let is_newline = match key.code {
+                KeyCode::Char('\n') | KeyCode::Char('\r') => true,
+                KeyCode::Char('j') => key.modifiers.contains(KeyModifiers::CONTROL),
+                _ => false,
+            };
+            if is_newline {
+                Event::Key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE))
+            } else {
+                Event::Key(key)
+            }
+        }
+        other => other,
+    }
+}
+
+// Executes each command in order without a terminal, stopping early on quit
+#[cfg(test)]
+fn run_commands(app: &mut App, script: &[&str]) {
+    for cmd in script {
+        if app.handle_command(cmd) == CommandResult::Quit {
+            break;
+        }
+    }
+}
+
+fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io::Result<()> {
+    let tick_rate = Duration::from_millis(app.config.tick_rate_ms.max(1));
+    let mut clock = TickClock::new(tick_rate, Instant::now());
+    let mut dirty_ui = true; // Only redraw when something visible has changed
+    let mut last_input = Instant::now();
+    let size = terminal.size()?;
+    app.resize(size.width, size.height);
+    loop {
+        if dirty_ui {
+            terminal.draw(|f| ui(f, &app))?;
+            dirty_ui = false;
+        }
+        // Written straight after a draw; BEL moves nothing on screen, so the
+        // next frame's diff stays accurate
+        if std::mem::take(&mut app.bell_pending) {
+            let mut stdout = io::stdout();
+            stdout.write_all(b"\x07")?;
+            stdout.flush()?;
+        }
+
+        let timeout = poll_timeout(last_input.elapsed(), clock.until_next(Instant::now()));
+
+        if crossterm::event::poll(timeout)? {
+            last_input = Instant::now();
+            // Drain every event that is already waiting so a multi-line paste
+            // is handled as one burst, with each line submitted in order
+            let mut burst_lines = 0;
+            let mut dropped_lines = 0;
+            loop {
+                match normalize_paste(event::read()?) {
+                    Event::Key(key) => {
+                        let key = app.config.keybindings.translate(key);
+                        dirty_ui = true;
+                        match app.state {
+                            // Nothing has been played yet, so there is nothing to lose
+                            AppState::Menu if app.is_quit_key(key) => {
+                                return app.save_history();
+                            }
+                            AppState::Menu => {
+                                if app.menu_key(key) == CommandResult::Quit {
+                                    return app.save_history();
+                                }
+                            }
+                            // Esc steps back out of the screen here, so only Ctrl-C quits
+                            AppState::Settings
+                                if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) =>
+                            {
+                                if app.request_quit() {
+                                    return app.save_history();
+                                }
+                            }
+                            AppState::Settings => {
+                                app.settings_key(key);
+                            }
+                            AppState::Game if app.is_quit_key(key) => {
+                                if app.request_quit() {
+                                    return app.save_history();
+                                }
+                            }
+                            // Nothing is left to lose once dead
+                            AppState::GameOver if app.is_quit_key(key) => {
+                                return app.save_history();
+                            }
+                            AppState::Game if app.pending_confirm.is_some() => {
+                                app.answer_confirm(key);
+                            }
+                            AppState::Game if app.composing => {
+                                app.compose_key(key);
+                            }
+                            AppState::Game if app.search.is_some() && key.code != KeyCode::Enter => {
+                                app.search_key(key);
+                            }
+                            AppState::Game => match key.code {
+                                KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                    app.search = Some(String::new());
+                                }
+                                KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                    app.take_input();
+                                }
+                                KeyCode::Char('w') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                    app.delete_word();
+                                }
+                                KeyCode::Char(c) => {
+                                    app.push_input(c);
+                                }
+                                KeyCode::Backspace => {
+                                    app.backspace();
+                                }
+                                KeyCode::Delete => {
+                                    app.delete_forward();
+                                }
+                                KeyCode::Left => {
+                                    app.move_cursor(-1);
+                                }
+                                KeyCode::Right => {
+                                    app.move_cursor(1);
+                                }
+                                KeyCode::Home => {
+                                    app.cursor_pos = 0;
+                                }
+                                KeyCode::PageUp => {
+                                    app.scroll_up();
+                                }
+                                KeyCode::PageDown => {
+                                    app.scroll_down();
+                                }
+                                // Also jumps the log back to the newest message
+                                KeyCode::End => {
+                                    app.cursor_pos = app.input.chars().count();
+                                    app.scroll_offset = 0;
+                                }
+                                KeyCode::Tab => {
+                                    app.complete_input();
+                                }
+                                KeyCode::Up => {
+                                    app.history_up();
+                                }
+                                KeyCode::Down => {
+                                    app.history_down();
+                                }
+                                KeyCode::Enter if burst_lines >= MAX_PASTE_LINES => {
+                                    app.take_input();
+                                    dropped_lines += 1;
+                                }
+                                KeyCode::Enter => {
+                                    burst_lines += 1;
+                                    app.accept_search();
+                                    let cmd = app.take_input();
+                                    if app.handle_command(&cmd) == CommandResult::Quit {
+                                        return app.save_history();
+                                    }
+                                }
+                                _ => {}
+                            },
+                            // Only restarting or quitting is possible once dead
+                            AppState::GameOver => match key.code {
+                                KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                    app.take_input();
+                                }
+                                KeyCode::Char('w') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                                    app.delete_word();
+                                }
+                                KeyCode::Char(c) => {
+                                    app.push_input(c);
+                                }
+                                KeyCode::Backspace => {
+                                    app.backspace();
+                                }
+                                KeyCode::Left => {
+                                    app.move_cursor(-1);
+                                }
+                                KeyCode::Right => {
+                                    app.move_cursor(1);
+                                }
+                                KeyCode::Enter => {
+                                    let cmd = app.take_input();
+                                    if app.handle_command(&cmd) == CommandResult::Quit {
+                                        return app.save_history();
+                                    }
+                                }
+                                _ => {}
+                            },
+                        }
+                    }
+                    Event::Resize(width, height) => {
+                        app.resize(width, height);
+                        dirty_ui = true;
+                    }
+                    _ => {}
+                }
+                if !crossterm::event::poll(Duration::from_secs(0))? {
+                    break;
+                }
+            }
+            if dropped_lines > 0 {
+                app.add_message(
+                    &format!("Paste truncated: skipped {} lines beyond the first {}.", dropped_lines, MAX_PASTE_LINES),
+                    MessageColor::Yellow,
+                );
+            }
+        }
+
+        // Runs every tick that has fallen due, several at once after an idle
+        // poll or a slow redraw, so game time follows the clock rather than the loop
+        for _ in 0..clock.due(Instant::now()) {
+            dirty_ui |= app.ui_tick();
+            // Game time stands still while paused and on the menu, settings
+            // and game over screens
+            if app.paused || app.state != AppState::Game {
+                continue;
+            }
+            app.tick();
+            let interval = app.config.autosave_interval;
+            if interval > 0 && app.tick_count.is_multiple_of(interval) {
+                app.autosave();
+            }
+            dirty_ui = true;
+        }
+    }
+}
+
+// Waits until the next tick normally, but backs off to a slower poll once the
+// player has been away for a while to save CPU
+fn poll_timeout(since_input: Duration, until_tick: Duration) -> Duration {
+    if since_input >= IDLE_AFTER {
+        IDLE_POLL.max(until_tick)
+    } else {
+        until_tick
+    }
+}
+
+// Fixed-timestep accumulator: elapsed real time is spent in whole ticks and
+// the leftover carries over, so no time is lost however unevenly the loop runs
+struct TickClock {
+    rate: Duration,
+    last: Instant, // When the most recent whole tick ended
+}
+
+impl TickClock {
+    fn new(rate: Duration, now: Instant) -> TickClock {
+        TickClock { rate: rate.max(Duration::from_millis(1)), last: now }
+    }
+
+    fn until_next(&self, now: Instant) -> Duration {
+        self.rate.saturating_sub(now.saturating_duration_since(self.last))
+    }
+
+    // How many ticks are due by `now`, which are then counted as run
+    fn due(&mut self, now: Instant) -> u32 {
+        let elapsed = now.saturating_duration_since(self.last);
+        let due = (elapsed.as_nanos() / self.rate.as_nanos()).try_into().unwrap_or(u32::MAX);
+        self.last += self.rate * due;
+        due
+    }
+}
+
+// Autocomplete suggestions with the typed prefix highlighted, truncated to
+// MAX_SUGGESTIONS so they don't overflow the input line
+fn suggestion_spans(app: &App) -> Vec<Span<'static>> {
+    if app.input.is_empty() {
+        return Vec::new();
+    }
+    let typed = app.input.to_lowercase();
+    let suggestions = app.get_autocomplete_suggestions();
+    let theme = app.display_theme();
+    let matched = Style::default()
+        .fg(theme.message_color(MessageColor::Yellow))
+        .add_modifier(Modifier::BOLD);
+    let remainder = Style::default().fg(theme.accent(Color::DarkGray));
+
+    let mut spans = vec![Span::styled(" [", remainder)];
+    for (i, suggestion) in suggestions.iter().take(MAX_SUGGESTIONS).enumerate() {
+        if i > 0 {
+            spans.push(Span::styled(", ", remainder));
+        }
+        // Alias expansions may not start with what was typed
+        let prefix_len = if suggestion.starts_with(&typed) { typed.len() } else { 0 };
+        let (prefix, rest) = suggestion.split_at(prefix_len);
+        spans.push(Span::styled(prefix.to_string(), matched));
+        spans.push(Span::styled(rest.to_string(), remainder));
+    }
+    spans.push(Span::styled("]", remainder));
+    if suggestions.len() > MAX_SUGGESTIONS {
+        spans.push(Span::styled(
+            format!(" (+{} more)", suggestions.len() - MAX_SUGGESTIONS),
+            remainder,
+        ));
+    }
+    spans
+}
+
+// Status bar and gauges, message log, events panel and input box, top to
+// bottom; composing trades log rows for a taller input box, and short
+// terminals get no events panel and a single-line input box
+fn main_layout(area: Rect, composing: bool) -> Vec<Rect> {
+    let compact = area.height < FULL_LAYOUT_HEIGHT;
+    let (messages, input) = if composing {
+        (Constraint::Min(0), Constraint::Length(COMPOSE_HEIGHT))
+    } else if compact {
+        (Constraint::Min(0), Constraint::Length(3))
+    } else {
+        (Constraint::Min(0), Constraint::Percentage(20))
+    };
+    let events = if compact { 0 } else { EVENT_PANEL_HEIGHT };
+    Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([Constraint::Length(2), messages, Constraint::Length(events), input].as_ref())
+        .split(area)
+}
+
+fn ui<B: Backend>(f: &mut tui::Frame<B>, app: &App) {
+    let area = f.size();
+    if area.width < MIN_TERMINAL_WIDTH || area.height < MIN_TERMINAL_HEIGHT {
+        // Checked on every draw, so the game comes back as soon as it fits
+        let theme = app.display_theme();
+        let middle = Rect::new(area.x, area.y + area.height / 2, area.width, area.height - area.height / 2);
+        f.render_widget(Block::default().style(theme.text()), area);
+        f.render_widget(
+            Paragraph::new("Terminal too small — please resize.")
+                .style(theme.text())
+                .alignment(Alignment::Center)
+                .wrap(Wrap { trim: true }),
+            middle,
+        );
+        return;
+    }
+    match app.state {
+        AppState::Menu => {
+            let theme = &app.display_theme();
+            let area = f.size();
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(
+                    [
+                        Constraint::Percentage(30),
+                        Constraint::Length(app.menu.len() as u16 + 4),
+                        Constraint::Min(0),
+                    ]
+                    .as_ref(),
+                )
+                .split(area);
+
+            let mut lines = vec![
+                Spans::from(Span::styled(
+                    "PYROBASE",
+                    Style::default()
+                        .fg(theme.accent(Color::Red))
+                        .add_modifier(Modifier::BOLD),
+                )),
+                Spans::from(""),
+            ];
+            for (i, item) in app.menu.iter().enumerate() {
+                lines.push(if i == app.menu_selected {
+                    Spans::from(Span::styled(
+                        format!("> {} <", item.label()),
+                        Style::default()
+                            .fg(theme.accent(Color::Yellow))
+                            .add_modifier(Modifier::BOLD),
+                    ))
+                } else {
+                    Spans::from(item.label())
+                });
+            }
+            lines.push(Spans::from(""));
+            lines.push(Spans::from("Up/Down to choose, Enter to select"));
+
+            f.render_widget(Block::default().style(theme.text()), area);
+            f.render_widget(
+                Paragraph::new(lines)
+                    .style(theme.text())
+                    .alignment(Alignment::Center),
+                rows[1],
+            );
+        }
+        AppState::Settings => {
+            let theme = &app.display_theme();
+            let area = f.size();
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(
+                    [
+                        Constraint::Percentage(30),
+                        Constraint::Length(SETTINGS.len() as u16 + 5),
+                        Constraint::Min(0),
+                    ]
+                    .as_ref(),
+                )
+                .split(area);
+
+            let mut labels = SETTINGS
+                .iter()
+                .map(|setting| {
+                    let state = if app.setting_enabled(*setting) { "on" } else { "off" };
+                    format!("{:<20}[{:>3}]", setting.label(), state)
+                })
+                .collect::<Vec<_>>();
+            labels.push(format!("{:<25}", "Back"));
+            let mut lines = vec![
+                Spans::from(Span::styled("Settings", Style::default().add_modifier(Modifier::BOLD))),
+                Spans::from(""),
+            ];
+            for (i, label) in labels.into_iter().enumerate() {
+                lines.push(if i == app.settings_selected {
+                    Spans::from(Span::styled(
+                        format!("> {} <", label),
+                        Style::default()
+                            .fg(theme.accent(Color::Yellow))
+                            .add_modifier(Modifier::BOLD),
+                    ))
+                } else {
+                    Spans::from(format!("  {}  ", label))
+                });
+            }
+            lines.push(Spans::from(""));
+            lines.push(Spans::from("Up/Down to choose, Enter or Space to toggle, Esc to go back"));
+
+            f.render_widget(Block::default().style(theme.text()), area);
+            f.render_widget(
+                Paragraph::new(lines)
+                    .style(theme.text())
+                    .alignment(Alignment::Center),
+                rows[1],
+            );
+        }
+        AppState::Game => {
+            let chunks = main_layout(f.size(), app.composing);
+
+            let theme = &app.display_theme();
+            let label = theme.text();
+            let value = theme.text()
+                .fg(theme.message_color(MessageColor::Yellow))
+                .add_modifier(Modifier::BOLD);
+            let (day, hour) = app.game_time();
+            let mut status_bar = Spans::from(vec![
+                Span::styled(" Day ", label),
+                Span::styled(format!("{} {:02}:00", day, hour), value),
+                Span::styled("  Tick: ", label),
+                Span::styled(app.tick_count.to_string(), value),
+                Span::styled("  Resources: ", label),
+                Span::styled(app.total_resources().to_string(), value),
+                Span::styled("  Weight: ", label),
+                Span::styled(format!("{}/{}", app.total_weight(), MAX_WEIGHT), value),
+                Span::styled("  State: ", label),
+                Span::styled(format!("{:?}", app.state), value),
+            ]);
+            if app.paused {
+                status_bar.0.push(Span::styled("  PAUSED", value));
+            }
+            if app.show_autosave_notice() {
+                let dim = Style::default().fg(theme.accent(Color::DarkGray)).add_modifier(Modifier::DIM);
+                status_bar.0.push(Span::styled("  autosaved", dim));
+            }
+
+            let header = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Length(1), Constraint::Length(1)].as_ref())
+                .split(chunks[0]);
+            f.render_widget(Paragraph::new(status_bar).style(theme.text()), header[0]);
+
+            let fire = app.fire_level();
+            let mut gauges = vec![
+                ("Health", app.health, MAX_HEALTH, threshold_color(app.health, MAX_HEALTH)),
+                ("Energy", app.energy, MAX_ENERGY, threshold_color(app.energy, MAX_ENERGY)),
+                ("Fire", u32::from(fire), u32::from(MAX_FIRE), fire_color(fire)),
+            ];
+            if let Some(construction) = app.constructions.first() {
+                let done = construction.total_ticks - construction.ticks_remaining;
+                let percent = (done * 100 / construction.total_ticks.max(1)) as u32;
+                gauges.push(("Building", percent, 100, MessageColor::Cyan));
+            }
+            let columns = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(vec![Constraint::Ratio(1, gauges.len() as u32); gauges.len()])
+                .split(header[1]);
+            for ((name, current, max, color), column) in gauges.into_iter().zip(columns) {
+                let gauge = Gauge::default()
+                    .gauge_style(theme.text().fg(theme.message_color(color)))
+                    .ratio(f64::from(current.min(max)) / f64::from(max.max(1)))
+                    .label(format!("{} {}/{}", name, current, max))
+                    .use_unicode(true);
+                // One column short so neighbouring bars don't run together; without
+                // colors only the label is visible, which still has the numbers
+                let area = Rect { width: column.width.saturating_sub(1), ..column };
+                f.render_widget(gauge, area);
+            }
+
+            let mut message_area = chunks[1];
+            if app.show_minimap {
+                let columns = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Min(0), Constraint::Length(MINIMAP_WIDTH)].as_ref())
+                    .split(chunks[1]);
+                message_area = columns[0];
+                let minimap = app
+                    .minimap_lines()
+                    .into_iter()
+                    .map(|line| Spans::from(Span::raw(line)))
+                    .collect::<Vec<_>>();
+                let minimap_widget = Paragraph::new(minimap)
+                    .block(theme.block("Map"))
+                    .style(theme.text())
+                    .alignment(Alignment::Center);
+                f.render_widget(minimap_widget, columns[1]);
+            }
+            let fitting = app.fitting_messages(
+                message_area.width.saturating_sub(2) as usize,
+                message_area.height.saturating_sub(2) as usize,
+            );
+            let thumb = scrollbar_thumb(
+                app.filtered_messages().count(),
+                fitting.len(),
+                app.scroll_offset,
+                message_area.height.saturating_sub(2) as usize,
+            );
+            let visible_messages = fitting
+                .into_iter()
+                .rev()
+                .map(|msg| {
+                    let style = theme.message_style(msg).add_modifier(app.message_modifiers(msg));
+                    let spans = parse_markup(&app.format_message(msg), style, theme);
+                    Spans::from(drop_trailing_chars(spans, msg.hidden))
+                })
+                .collect::<Vec<_>>();
+
+            let title = match app.filter {
+                Some(color) => format!("Messages (filter: {:?})", color).to_lowercase(),
+                None => "Messages".to_string(),
+            };
+            let messages_widget = Paragraph::new(visible_messages)
+                .block(theme.block(&title))
+                .style(theme.text())
+                .wrap(Wrap { trim: true });
+
+            f.render_widget(messages_widget, message_area);
+
+            // Drawn over the right border, newest messages at the top like the log
+            if let Some((start, len)) = thumb {
+                let track = Rect::new(
+                    message_area.right().saturating_sub(1),
+                    message_area.y + 1,
+                    1,
+                    message_area.height.saturating_sub(2),
+                );
+                let bar = (0..track.height as usize)
+                    .map(|row| Spans::from(if (start..start + len).contains(&row) { "█" } else { "│" }))
+                    .collect::<Vec<_>>();
+                f.render_widget(Paragraph::new(bar).style(Style::default().fg(theme.border)), track);
+            }
+
+            let events = app
+                .events
+                .iter()
+                .rev()
+                .take(chunks[2].height.saturating_sub(2) as usize)
+                .map(|event| {
+                    let style = theme.message_style(event).add_modifier(event.modifiers());
+                    Spans::from(parse_markup(&app.format_message(event), style, theme))
+                })
+                .collect::<Vec<_>>();
+            let events_widget = Paragraph::new(events)
+                .block(theme.block("Events"))
+                .style(theme.text());
+            f.render_widget(events_widget, chunks[2]);
+
+            let cursor = app.cursor_span(theme);
+            let input_content = match &app.search {
+                Some(query) => vec![
+                    Span::raw(format!("(reverse-i-search)`{}': ", query)),
+                    Span::raw(app.search_match().cloned().unwrap_or_default()),
+                    cursor,
+                ],
+                None => {
+                    let prompt = Style::default().fg(theme.accent(Color::DarkGray));
+                    let mut spans = vec![Span::styled(format!("{}> ", app.location), prompt)];
+                    spans.extend(app.input_spans(theme));
+                    spans.extend(suggestion_spans(app));
+                    spans
+                }
+            };
+
+            let title = if app.composing { "Journal entry (Ctrl-D saves, Esc cancels)" } else { "Input" };
+            let mut input_block = theme.block(title);
+            if app.input_overflow || app.error_flash() {
+                input_block = input_block.border_style(Style::default().fg(theme.accent(Color::Red)));
+            }
+            let input_lines = if app.composing {
+                let mut lines = app.input.split('\n').map(Spans::from).collect::<Vec<_>>();
+                if let Some(last) = lines.last_mut() {
+                    last.0.push(app.cursor_span(theme));
+                }
+                lines
+            } else {
+                vec![Spans::from(input_content)]
+            };
+            let input_widget = Paragraph::new(input_lines)
+                .block(input_block)
+                .style(theme.text())
+                .wrap(Wrap { trim: false });
+
+            f.render_widget(input_widget, chunks[3]);
+        }
+        AppState::GameOver => {
+            let theme = &app.display_theme();
+            let area = f.size();
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(
+                    [
+                        Constraint::Percentage(40),
+                        Constraint::Length(5),
+                        Constraint::Min(0),
+                    ]
+                    .as_ref(),
+                )
+                .split(area);
+
+            let banner = vec![
+                Spans::from(Span::styled(
+                    "You have perished.",
+                    Style::default()
+                        .fg(theme.message_color(MessageColor::Red))
+                        .add_modifier(Modifier::BOLD),
+                )),
+                Spans::from("Type 'restart' or 'quit'."),
+                Spans::from(""),
+                Spans::from([vec![Span::raw("> ")], app.input_spans(theme)].concat()),
+            ];
+
+            f.render_widget(Block::default().style(theme.text()), area);
+            f.render_widget(
+                Paragraph::new(banner)
+                    .style(theme.text())
+                    .alignment(Alignment::Center),
+                rows[1],
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A file of the test's own in the temp dir, so tests running in parallel
+    // or in another checkout at the same time don't clobber each other
+    fn temp_path(test: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("pyrobase_{}_test_{}.txt", test, std::process::id()))
+    }
+
+    #[test]
+    fn save_round_trip_preserves_messages() {
+        let mut app = App::new();
+        app.add_message("Collected 1 firestone", MessageColor::Green);
+
+        let json = serde_json::to_string_pretty(&app).unwrap();
+        let loaded: App = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(loaded.messages, app.messages);
+    }
+
+    #[test]
+    fn rgb_message_round_trips_through_json() {
+        let message = StoredMessage {
+            underline: true,
+            ..StoredMessage::new("The embers glow", MessageColor::Rgb(255, 120, 0), 7)
+        };
+
+        let json = serde_json::to_string(&message).unwrap();
+        let loaded: StoredMessage = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(loaded, message);
+        assert_eq!(loaded.color.to_color(), Color::Rgb(255, 120, 0));
+        assert_eq!(loaded.modifiers(), Modifier::UNDERLINED);
+
+        let old: StoredMessage = serde_json::from_str(r#"{"content":"Game saved.","color":"Green"}"#).unwrap();
+        assert_eq!(old.modifiers(), Modifier::empty());
+    }
+
+    #[test]
+    fn config_fills_missing_fields_with_defaults() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.tick_rate_ms, Config::default().tick_rate_ms);
+
+        let config: Config = toml::from_str("tick_rate_ms = 200").unwrap();
+        assert_eq!(config.tick_rate_ms, 200);
+    }
+
+    #[test]
+    fn game_time_rolls_hours_into_days() {
+        let mut app = App::new();
+        app.config.ticks_per_hour = 10;
+        assert_eq!(app.game_time(), (1, 0));
+
+        app.tick_count = 10 * 23 + 9;
+        assert_eq!(app.game_time(), (1, 23));
+        app.tick_count = 10 * 24;
+        assert_eq!(app.game_time(), (2, 0));
+    }
+
+    #[test]
+    fn ring_buffer_keeps_logical_order_after_wrapping() {
+        let mut app = App::new();
+        for i in 1..=1005 {
+            app.add_message(&format!("message {}", i), MessageColor::White);
+        }
+
+        let recent = app.fitting_messages(80, 10)
+            .into_iter()
+            .map(|msg| msg.content.clone())
+            .collect::<Vec<_>>();
+        let expected = (996..=1005)
+            .map(|i| format!("message {}", i))
+            .collect::<Vec<_>>();
+
+        assert_eq!(app.messages.len(), DEFAULT_MAX_MESSAGES);
+        assert_eq!(recent, expected);
+    }
+
+    #[test]
+    fn log_size_can_shrink_and_grow_a_wrapped_log() {
+        let mut app = App::new();
+        app.set_max_messages(3);
+        assert_eq!(app.max_messages, MIN_MESSAGES);
+        for i in 1..=15 {
+            app.add_message(&format!("message {}", i), MessageColor::White);
+        }
+        let contents = |app: &App| app.ordered_messages().map(|m| m.content.clone()).collect::<Vec<_>>();
+        assert_eq!(contents(&app)[0], "message 6");
+
+        app.set_max_messages(12);
+        assert_eq!(contents(&app).len(), MIN_MESSAGES);
+        assert_eq!(contents(&app)[0], "message 6");
+        app.add_message("message 16", MessageColor::White);
+        assert_eq!(contents(&app).last().map(String::as_str), Some("message 16"));
+
+        app.set_max_messages(MIN_MESSAGES);
+        assert_eq!(contents(&app)[0], "message 7");
+        assert_eq!(contents(&app).len(), MIN_MESSAGES);
+    }
+
+    #[test]
+    fn scrolling_is_clamped_to_message_history() {
+        let mut app = App::new();
+        for i in 0..23 {
+            app.add_message(&format!("message {}", i), MessageColor::White);
+        }
+
+        app.scroll_down();
+        assert_eq!(app.scroll_offset, 0);
+        assert_eq!(app.page_height, VISIBLE_MESSAGES);
+        app.scroll_up();
+        assert_eq!(app.scroll_offset, 10);
+        app.scroll_up();
+        app.scroll_up();
+        assert_eq!(app.scroll_offset, app.messages.len() - VISIBLE_MESSAGES);
+        let oldest = &app.fitting_messages(80, VISIBLE_MESSAGES)[0];
+        assert_eq!(oldest.content, "Welcome to Pyrobase. Type 'help' for commands.");
+    }
+
+    #[test]
+    fn resize_reclamps_scroll_offset() {
+        let mut app = App::new();
+        for i in 0..30 {
+            app.add_message(&format!("message {}", i), MessageColor::White);
+        }
+        app.scroll_offset = app.max_scroll_offset();
+
+        app.resize(80, 40);
+        assert!(app.page_height > VISIBLE_MESSAGES);
+        assert_eq!(app.scroll_offset, app.messages.len() - app.page_height);
+    }
+
+    #[test]
+    fn markup_colors_parts_of_a_message() {
+        let theme = Theme::default();
+        let base = Style::default().fg(Color::White);
+        let spans = parse_markup("Found {green:fire crystal}!", base, &theme);
+        let parts = spans.iter().map(|span| (span.content.as_ref(), span.style.fg)).collect::<Vec<_>>();
+        assert_eq!(parts, vec![
+            ("Found ", Some(Color::White)),
+            ("fire crystal", Some(Color::Green)),
+            ("!", Some(Color::White)),
+        ]);
+
+        for literal in ["{green:open", "{pink:text}", "a {b} c"] {
+            assert_eq!(strip_markup(literal), literal);
+        }
+        assert_eq!(strip_markup("{{red:x}"), "{x");
+    }
+
+    #[test]
+    fn parse_amount_explains_what_is_wrong() {
+        assert_eq!(parse_amount("12"), Ok(12));
+        assert_eq!(parse_amount("007"), Ok(7));
+        assert_eq!(parse_amount("abc"), Err("'abc' is not a valid number.".to_string()));
+        assert_eq!(parse_amount(""), Err("'' is not a valid number.".to_string()));
+        assert_eq!(parse_amount("1.5"), Err("'1.5' is not a valid number.".to_string()));
+        assert_eq!(parse_amount("-"), Err("'-' is not a valid number.".to_string()));
+        assert_eq!(parse_amount("-3"), Err("'-3' is negative; amounts start at 1.".to_string()));
+        assert_eq!(parse_amount("0"), Err("The amount must be at least 1.".to_string()));
+        assert_eq!(parse_amount("99999999999"), Err("'99999999999' is too large.".to_string()));
+
+        let mut app = App::new();
+        app.handle_command("rest abc");
+        assert_eq!(app.messages[app.message_index].content, "'abc' is not a valid number.");
+        assert_eq!(app.messages[app.message_index].color, MessageColor::Red);
+    }
+
+    #[test]
+    fn wrapped_line_count_breaks_on_words() {
+        assert_eq!(wrapped_line_count("short", 20), 1);
+        assert_eq!(wrapped_line_count("gather the firestone now", 10), 3);
+        assert_eq!(wrapped_line_count("aaaaaaaaaaaaaaaaaaaaaaaaa", 10), 3);
+        assert_eq!(wrapped_line_count("", 10), 1);
+    }
+
+    #[test]
+    fn fitting_messages_accounts_for_wrapping() {
+        let mut app = App::new();
+        app.clear_messages();
+        app.add_message("one two three four five six", MessageColor::White);
+        app.add_message("tiny", MessageColor::White);
+
+        let fitting = app.fitting_messages(12, 3);
+        assert_eq!(fitting.len(), 1);
+        assert_eq!(fitting[0].content, "tiny");
+    }
+
+    #[test]
+    fn scrollbar_tracks_the_scroll_offset() {
+        assert_eq!(scrollbar_thumb(5, 5, 0, 10), None);
+        assert_eq!(scrollbar_thumb(40, 10, 0, 10), Some((0, 2)));
+        assert_eq!(scrollbar_thumb(40, 10, 30, 10), Some((8, 2)));
+        assert_eq!(scrollbar_thumb(40, 10, 15, 10), Some((4, 2)));
+        assert_eq!(scrollbar_thumb(1000, 10, 0, 10), Some((0, 1)));
+    }
+
+    #[test]
+    fn filter_shows_only_one_color() {
+        let mut app = App::new();
+        app.add_message("Too tired.", MessageColor::Red);
+        app.add_message("Gathered 5 firestone", MessageColor::Green);
+        app.handle_command("filter RED");
+        let shown = app.fitting_messages(80, 10).iter().map(|m| m.content.clone()).collect::<Vec<_>>();
+        assert_eq!(shown, vec!["Too tired.", "Showing only red messages. Type 'filter off' to see all."]);
+
+        app.handle_command("filter mauve");
+        assert_eq!(app.filter, Some(MessageColor::Red));
+        app.handle_command("filter off");
+        assert_eq!(app.fitting_messages(80, 10).len(), app.messages.len());
+    }
+
+    #[test]
+    fn old_messages_are_dimmed() {
+        let mut app = App::new();
+        app.add_bold_message("You have perished in the flames.", MessageColor::Red);
+        let msg = app.messages.last().unwrap().clone();
+        assert_eq!(app.message_modifiers(&msg), Modifier::BOLD);
+
+        app.tick_count += STALE_MESSAGE_TICKS + 1;
+        assert_eq!(app.message_modifiers(&msg), Modifier::BOLD | Modifier::DIM);
+    }
+
+    #[test]
+    fn timestamps_prefix_elapsed_seconds() {
+        let mut app = App::new();
+        app.tick_count = 1000 / app.config.tick_rate_ms * 12;
+        app.add_message("Gathered 5 firestone", MessageColor::Green);
+        let msg = app.messages[app.message_index].clone();
+
+        assert_eq!(app.format_message(&msg), "> Gathered 5 firestone");
+        app.config.timestamps = true;
+        assert_eq!(app.format_message(&msg), "> [0012s] Gathered 5 firestone");
+    }
+
+    #[test]
+    fn theme_persists_by_name_and_mono_ignores_colors() {
+        let mut app = App::new();
+        app.handle_command("theme mono");
+        assert_eq!(app.theme.message_color(MessageColor::Green), Color::Gray);
+
+        let json = serde_json::to_string(&app).unwrap();
+        assert!(json.contains("\"theme\":\"mono\""));
+        let loaded: App = serde_json::from_str(&json).unwrap();
+        assert!(loaded.theme.monochrome);
+    }
+
+    #[test]
+    fn palette_survives_theme_changes_and_saves() {
+        let mut app = App::new();
+        run_commands(&mut app, &["theme palette deuteranopia", "theme highcontrast"]);
+        assert_eq!(app.theme.palette, Palette::Deuteranopia);
+        assert_eq!(app.theme.message_color(MessageColor::Red), Color::Rgb(230, 159, 0));
+        assert_eq!(app.theme.message_color(MessageColor::Cyan), Color::Cyan);
+
+        let json = serde_json::to_string(&app).unwrap();
+        assert!(json.contains("\"theme\":\"highcontrast:deuteranopia\""));
+        let loaded: App = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.theme.name, "highcontrast");
+        assert_eq!(loaded.theme.palette, Palette::Deuteranopia);
+    }
+
+    #[test]
+    fn export_writes_full_log_with_color_tags() {
+        let mut app = App::new();
+        app.clear_messages();
+        app.add_message("Gathered 5 firestone", MessageColor::Green);
+        let path = temp_path("export");
+
+        app.export_log(path.to_str().unwrap());
+
+        let exported = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(exported, "[White] Log cleared.\n[Green] Gathered 5 firestone\n");
+    }
+
+    #[test]
+    fn clear_resets_ring_buffer_bookkeeping() {
+        let mut app = App::new();
+        for i in 0..1200 {
+            app.add_message(&format!("message {}", i), MessageColor::White);
+        }
+        app.clear_messages();
+        app.add_message("after clear", MessageColor::Green);
+
+        let contents = app.ordered_messages().map(|msg| msg.content.as_str()).collect::<Vec<_>>();
+        assert_eq!(contents, vec!["Log cleared.", "after clear"]);
+        assert_eq!(app.message_index, 1);
+    }
+
+    #[test]
+    fn history_navigation_skips_duplicates_and_restores_empty_input() {
+        let mut app = App::new();
+        for command in ["help", "help", "save"] {
+            app.last_command = command.to_string();
+            app.record_history();
+        }
+        assert_eq!(app.history, vec!["help", "save"]);
+
+        app.history_up();
+        assert_eq!(app.input, "save");
+        app.history_up();
+        app.history_up();
+        assert_eq!(app.input, "help");
+        app.history_down();
+        assert_eq!(app.input, "save");
+        app.history_down();
+        assert_eq!(app.input, "");
+    }
+
+    #[test]
+    fn pasted_newlines_become_enter() {
+        let key = |code, modifiers| Event::Key(KeyEvent::new(code, modifiers));
+        let enter = key(KeyCode::Enter, KeyModifiers::NONE);
+
+        assert_eq!(normalize_paste(key(KeyCode::Char('\n'), KeyModifiers::NONE)), enter);
+        assert_eq!(normalize_paste(key(KeyCode::Char('j'), KeyModifiers::CONTROL)), enter);
+        assert_eq!(
+            normalize_paste(key(KeyCode::Char('j'), KeyModifiers::NONE)),
+            key(KeyCode::Char('j'), KeyModifiers::NONE)
+        );
+    }
+
+    #[test]
+    fn input_stops_growing_at_the_limit() {
+        let mut app = App::new();
+        for _ in 0..MAX_INPUT_LEN + 5 {
+            app.push_input('a');
+        }
+        assert_eq!(app.input.len(), MAX_INPUT_LEN);
+        assert!(app.input_overflow);
+
+        // Cleared by the UI clock, which runs while paused
+        app.handle_command("pause");
+        assert!(app.ui_tick());
+        app.backspace();
+        app.push_input('b');
+        assert!(!app.input_overflow);
+        assert!(app.input.ends_with('b'));
+    }
+
+    #[test]
+    fn autocomplete_ignores_case() {
+        let mut app = App::new();
+        for input in ["h", "H", "HE"] {
+            app.input = input.to_string();
+            assert_eq!(app.get_autocomplete_suggestions(), vec!["help"], "input {:?}", input);
+        }
+    }
+
+    #[test]
+    fn parse_command_splits_words_and_quotes() {
+        assert_eq!(parse_command("GO north"), ("go".to_string(), vec!["north".to_string()]));
+        assert_eq!(
+            parse_command("gather \"fire crystal\""),
+            ("gather".to_string(), vec!["fire crystal".to_string()])
+        );
+        assert_eq!(
+            parse_command("  alias   n    go  "),
+            ("alias".to_string(), vec!["n".to_string(), "go".to_string()])
+        );
+        assert_eq!(parse_command("note \"\""), ("note".to_string(), vec![String::new()]));
+        assert_eq!(parse_command(""), (String::new(), vec![]));
+        assert_eq!(parse_command("   "), (String::new(), vec![]));
+    }
+
+    #[test]
+    fn reverse_search_finds_most_recent_match() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let mut app = App::new();
+        app.history = vec!["go north".to_string(), "gather firestone".to_string(), "go east".to_string()];
+
+        app.search = Some(String::new());
+        assert_eq!(app.search_match(), None);
+        app.search_key(key(KeyCode::Char('g')));
+        app.search_key(key(KeyCode::Char('o')));
+        assert_eq!(app.search_match().map(String::as_str), Some("go east"));
+        app.search_key(key(KeyCode::Char(' ')));
+        app.search_key(key(KeyCode::Char('n')));
+        app.accept_search();
+        assert_eq!(app.input, "go north");
+        assert_eq!(app.search, None);
+
+        app.search = Some("zzz".to_string());
+        app.search_key(key(KeyCode::Esc));
+        assert_eq!(app.search, None);
+        assert_eq!(app.input, "go north");
+    }
+
+    #[test]
+    fn history_is_capped_by_dropping_the_oldest() {
+        let mut app = App::new();
+        for i in 0..MAX_HISTORY + 3 {
+            app.last_command = format!("go {}", i);
+            app.record_history();
+        }
+        assert_eq!(app.history.len(), MAX_HISTORY);
+        assert_eq!(app.history[0], "go 3");
+    }
+
+    #[test]
+    fn suggestions_highlight_prefix_and_truncate() {
+        let mut app = App::new();
+        app.input = "Ga".to_string();
+        let text = |spans: &[Span]| spans.iter().map(|span| span.content.to_string()).collect::<Vec<_>>();
+
+        let spans = suggestion_spans(&app);
+        assert_eq!(text(&spans), vec![" [", "ga", "ther", "]"]);
+        assert_ne!(spans[1].style, spans[2].style);
+
+        app.commands = (0..8).map(|i| (format!("gather{}", i), command(|_, _| {}))).collect();
+        let spans = suggestion_spans(&app);
+        assert_eq!(spans.last().unwrap().content, " (+3 more)");
+    }
+
+    #[test]
+    fn longest_common_prefix_of_commands() {
+        let words = |list: &[&str]| list.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(longest_common_prefix(&words(&["save", "say", "sail"])), "sa");
+        assert_eq!(longest_common_prefix(&words(&["load"])), "load");
+        assert_eq!(longest_common_prefix(&words(&["quit", "help"])), "");
+        assert_eq!(longest_common_prefix(&[]), "");
+    }
+
+    #[test]
+    fn gather_only_accepts_known_resources() {
+        let mut app = App::new();
+        app.gather("firestone");
+        app.gather("firestone");
+        app.gather("driftwood");
+
+        assert_eq!(app.inventory.get("firestone").copied(), Some(app.resources_gathered as u32));
+        assert!(!app.inventory.contains_key("driftwood"));
+    }
+
+    #[test]
+    fn gather_yields_are_random_but_reproducible() {
+        let gather_all = |seed| {
+            let mut app = App::new();
+            app.reseed(seed);
+            let yields = (0..8)
+                .map(|_| {
+                    app.energy = MAX_ENERGY;
+                    app.inventory.clear();
+                    app.gather("heatcores");
+                    app.inventory["heatcores"]
+                })
+                .collect::<Vec<_>>();
+            (app, yields)
+        };
+        let (mut app, yields) = gather_all(42);
+        assert_eq!(yields, gather_all(42).1);
+        assert!(yields.iter().all(|amount| (1..=4).contains(amount)));
+        assert!(yields.iter().any(|amount| *amount != yields[0]));
+
+        // A loaded save carries on from where the sequence left off
+        let mut loaded: App = serde_json::from_str(&serde_json::to_string(&app).unwrap()).unwrap();
+        loaded.restore_rng();
+        assert_eq!(loaded.roll((1, 1000)), app.roll((1, 1000)));
+    }
+
+    #[test]
+    fn gathering_past_capacity_is_blocked() {
+        let mut app = App::new();
+        app.inventory.insert("firestone".to_string(), 93);
+        app.inventory.insert("blaze_hammer".to_string(), 1);
+        assert_eq!(app.total_weight(), 93 * 2 + TOOL_WEIGHT);
+
+        // Refused if the largest possible yield wouldn't fit
+        app.gather("firestone");
+        assert_eq!(app.inventory["firestone"], 93);
+        assert_eq!(app.energy, MAX_ENERGY);
+        assert_eq!(app.messages.last().map(|m| m.content.as_str()), Some("Too heavy — drop something first."));
+        app.gather("emberash");
+        assert!(app.inventory.contains_key("emberash"));
+        assert!(app.total_weight() <= MAX_WEIGHT);
+
+        app.handle_command("drop firestone 10");
+        app.gather("firestone");
+        assert!(app.inventory["firestone"] > 83);
+        app.handle_command("drop blaze_hammer");
+        assert!(!app.inventory.contains_key("blaze_hammer"));
+    }
+
+    #[test]
+    fn drop_removes_emptied_items_and_warns_on_overdrop() {
+        let mut app = App::new();
+        app.inventory.insert("emberash".to_string(), 7);
+        app.handle_command("drop emberash 2");
+        assert_eq!(app.inventory["emberash"], 5);
+        assert_eq!(app.messages.last().map(|m| m.content.as_str()), Some("Dropped 2 emberash."));
+
+        app.handle_command("drop emberash 9");
+        assert!(!app.inventory.contains_key("emberash"));
+        let last = app.messages.last().unwrap();
+        assert_eq!(last.content, "You only had 5 emberash, so you dropped all of it.");
+        assert_eq!(last.color, MessageColor::Yellow);
+
+        app.handle_command("drop emberash");
+        let last = app.messages.last().unwrap();
+        assert_eq!(last.content, "You don't have any emberash.");
+        assert_eq!(last.color, MessageColor::Red);
+    }
+
+    #[test]
+    fn tiring_actions_are_refused_until_rested() {
+        let mut app = App::new();
+        app.energy = GATHER_ENERGY + MOVE_ENERGY - 1;
+        app.gather("emberash");
+        app.go("north");
+        assert_eq!(app.location, "Entrance");
+        assert_eq!(app.energy, MOVE_ENERGY - 1);
+        assert_eq!(app.messages.last().map(|m| m.content.as_str()), Some("Too tired. Type 'rest' to recover."));
+
+        app.handle_command("rest");
+        assert_eq!(app.energy, MOVE_ENERGY - 1 + (app.config.ticks_per_hour / ENERGY_REGEN_INTERVAL) as u32);
+        app.go("north");
+        assert_eq!(app.location, "Scorched Plains");
+    }
+
+    #[test]
+    fn rest_advances_hours_and_summarizes() {
+        let mut app = App::new();
+        app.energy = 0;
+        app.handle_command("rest 3");
+        assert_eq!(app.tick_count, 3 * app.config.ticks_per_hour);
+        assert_eq!(app.energy, 60);
+        assert_eq!(
+            app.messages.last().map(|m| m.content.as_str()),
+            Some("You rest for 3 hours, recovering 60 energy. The fire spread in 5 locations.")
+        );
+
+        run_commands(&mut app, &["rest 0", "rest 13", "rest soon"]);
+        assert_eq!(app.tick_count, 3 * app.config.ticks_per_hour);
+    }
+
+    #[test]
+    fn lore_is_revealed_one_line_per_delay() {
+        let mut app = App::new();
+        app.handle_command("lore");
+        let delay = app.config.lore_delay_ms / app.config.tick_rate_ms;
+        app.tick();
+        assert_eq!(app.messages.last().map(|m| m.content.as_str()), Some(DEFAULT_LORE[0]));
+
+        for _ in 0..delay - 1 {
+            app.tick();
+        }
+        assert_eq!(app.messages.len(), 3);
+        app.tick();
+        assert_eq!(app.messages.last().map(|m| m.content.as_str()), Some(DEFAULT_LORE[1]));
+
+        for _ in 0..delay * DEFAULT_LORE.len() as u64 {
+            app.tick();
+        }
+        assert!(app.lore_queue.is_empty());
+        assert_eq!(app.messages.len(), 2 + DEFAULT_LORE.len() - 1);
+    }
+
+    #[test]
+    fn old_saves_migrate_to_the_current_version() {
+        let save = serde_json::json!({
+            "state": "Game",
+            "input": "",
+            "last_command": "gather firestone",
+            "commands": ["gather"],
+            "messages": [],
+            "message_index": 0,
+            "inventory": { "firestone": 5 },
+        });
+        let (app, from) = migrate_save(save).unwrap();
+        assert_eq!(from, 1);
+        assert_eq!(app.version, SAVE_VERSION);
+        assert_eq!(app.inventory.get("firestone"), Some(&5));
+        assert_eq!(app.health, MAX_HEALTH);
+        assert_eq!(app.location, "Entrance");
+
+        let current = serde_json::to_value(App::new()).unwrap();
+        assert_eq!(migrate_save(current).unwrap().1, SAVE_VERSION);
+        assert!(migrate_save(serde_json::json!({ "version": SAVE_VERSION + 1 })).is_err());
+    }
+
+    #[test]
+    fn autosave_notice_fades_after_a_moment() {
+        let mut app = App::new();
+        assert!(!app.show_autosave_notice());
+        app.autosaved_at = Some(app.tick_count);
+        assert!(app.show_autosave_notice());
+        for _ in 0..AUTOSAVE_NOTICE_TICKS {
+            app.tick();
+        }
+        assert!(!app.show_autosave_notice());
+    }
+
+    #[test]
+    fn pause_blocks_rest_until_resumed() {
+        let mut app = App::new();
+        run_commands(&mut app, &["pause", "rest", "inventory"]);
+        assert!(app.paused);
+        assert_eq!(app.tick_count, 0);
+
+        run_commands(&mut app, &["resume", "rest"]);
+        assert!(!app.paused);
+        assert_eq!(app.tick_count, app.config.ticks_per_hour);
+    }
+
+    #[test]
+    fn identical_messages_collapse_into_a_counter() {
+        let mut app = App::new();
+        let before = app.messages.len();
+        for _ in 0..3 {
+            app.add_message("You can't go that way.", MessageColor::Red);
+        }
+        assert_eq!(app.messages.len(), before + 1);
+        let last = app.ordered_messages().next_back().unwrap();
+        assert_eq!(app.format_message(last), "> You can't go that way. (x3)");
+
+        app.add_message("You can't go that way.", MessageColor::Yellow);
+        assert_eq!(app.messages.len(), before + 2);
+    }
+
+    #[test]
+    fn validate_repairs_out_of_range_save_fields() {
+        let mut save = serde_json::to_value(App::new()).unwrap();
+        save["message_index"] = 500.into();
+        save["location"] = "Atlantis".into();
+        save["health"] = 900.into();
+        save["today"]["gathered_before"] = 50.into();
+        let (mut app, _) = migrate_save(save).unwrap();
+        assert_eq!(app.validate(), vec!["message position", "location", "health and energy", "day summary"]);
+        assert_eq!(app.message_index, app.messages.len() - 1);
+        assert_eq!((app.location.as_str(), app.health), ("Entrance", MAX_HEALTH));
+
+        app.add_message("still works", MessageColor::White);
+        assert_eq!(app.ordered_messages().next_back().unwrap().content, "still works");
+        assert!(app.validate().is_empty());
+    }
+
+    #[test]
+    fn validate_bounds_rolls_and_ends_games_without_health() {
+        let mut save = serde_json::to_value(App::new()).unwrap();
+        save["rolls"] = u64::MAX.into();
+        save["health"] = 0.into();
+        let (mut app, _) = migrate_save(save).unwrap();
+        assert_eq!(app.validate(), vec!["random number state", "game over"]);
+        assert_eq!((app.rolls, app.state), (MAX_ROLLS, AppState::GameOver));
+        assert!(app.validate().is_empty());
+    }
+
+    #[test]
+    fn save_slots_are_numbered_one_to_five() {
+        let mut app = App::new();
+        assert_eq!(app.parse_slot(""), Some(1));
+        assert_eq!(app.parse_slot("5"), Some(5));
+        assert_eq!(save_path(3), "pyrobase_save_3.json");
+
+        run_commands(&mut app, &["save 6", "load 6", "save 99"]);
+        let last = app.ordered_messages().next_back().unwrap();
+        assert_eq!((last.content.as_str(), last.color, last.count), ("Save slots run from 1 to 5.", MessageColor::Red, 3));
+
+        // Anything that isn't a number gets the same error as other commands
+        run_commands(&mut app, &["save first", "load -2"]);
+        let errors = app.ordered_messages().rev().take(2).map(|msg| (msg.content.as_str(), msg.color)).collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![("'-2' is negative; amounts start at 1.", MessageColor::Red), ("'first' is not a valid number.", MessageColor::Red)]
+        );
+    }
+
+    #[test]
+    fn registered_commands_are_dispatched() {
+        let mut app = App::new();
+        app.commands.insert(
+            "shout".to_string(),
+            command(|app, args| app.add_message(&args.join(" ").to_uppercase(), MessageColor::White)),
+        );
+        app.handle_command("shout hello there");
+        assert_eq!(app.ordered_messages().next_back().unwrap().content, "HELLO THERE");
+
+        app.input = "sh".to_string();
+        assert_eq!(app.get_autocomplete_suggestions(), vec!["shout"]);
+        assert_eq!(app.handle_command("q"), CommandResult::Quit);
+    }
+
+    #[test]
+    fn delete_word_trims_back_to_whitespace() {
+        let mut app = App::new();
+        app.set_input("gather fire crystal  ".to_string());
+        app.delete_word();
+        assert_eq!(app.input, "gather fire ");
+        app.delete_word();
+        app.delete_word();
+        assert_eq!(app.input, "");
+        app.delete_word();
+        assert_eq!(app.input, "");
+    }
+
+    #[test]
+    fn input_is_edited_at_the_cursor() {
+        let mut app = App::new();
+        app.set_input("go nrth".to_string());
+        app.move_cursor(-3);
+        app.push_input('o');
+        assert_eq!((app.input.as_str(), app.cursor()), ("go north", 5));
+
+        app.backspace();
+        app.backspace();
+        app.delete_forward();
+        assert_eq!((app.input.as_str(), app.cursor()), ("go th", 3));
+        app.push_input(' ');
+        app.delete_word();
+        assert_eq!((app.input.as_str(), app.cursor()), ("th", 0));
+
+        app.move_cursor(-1);
+        assert_eq!(app.cursor(), 0);
+        app.move_cursor(10);
+        assert_eq!(app.cursor(), 2);
+        let text = app.input_spans(&Theme::default()).iter().map(|span| span.content.to_string()).collect::<Vec<_>>();
+        assert_eq!(text, vec!["th", "_", ""]);
+
+        // Recalling history puts the cursor back at the end
+        app.history = vec!["gather firestone".to_string()];
+        app.cursor_pos = 0;
+        app.history_up();
+        assert_eq!(app.cursor(), "gather firestone".len());
+    }
+
+    #[test]
+    fn repeat_reruns_the_previous_command() {
+        let mut app = App::new();
+        app.handle_command("repeat");
+        assert_eq!(app.ordered_messages().next_back().unwrap().content, "Nothing to repeat.");
+
+        app.handle_command("gather firestone");
+        let once = app.inventory["firestone"];
+        app.handle_command("repeat");
+        app.handle_command("!!");
+        assert!(app.inventory["firestone"] > once);
+        let echo = app.ordered_messages().find(|msg| msg.content == "gather firestone").unwrap();
+        assert!(echo.modifiers().contains(Modifier::DIM));
+        assert_eq!(app.last_command, "gather firestone");
+        assert_eq!(app.history, vec!["gather firestone"]);
+        assert_eq!(app.undo_stack.len(), 3);
+        assert_eq!(app.commands_entered, 3);
+    }
+
+    #[test]
+    fn difficulty_scales_yields_costs_and_fire() {
+        let mut easy = App::new();
+        easy.difficulty = Difficulty::Easy;
+        let mut hard = App::new();
+        hard.difficulty = Difficulty::Hard;
+        assert_eq!(easy.difficulty.gather_range("heatcores"), (1, 6));
+        assert_eq!(hard.difficulty.gather_range("heatcores"), (1, 3));
+
+        easy.gather("firestone");
+        hard.gather("firestone");
+        assert!(hard.inventory["firestone"] <= hard.difficulty.gather_range("firestone").1);
+        assert!(easy.energy > hard.energy);
+        assert!(easy.difficulty.fire_spread_interval() > hard.difficulty.fire_spread_interval());
+
+        // Saves from before difficulty levels play on normal, and restarting keeps the choice
+        let mut save = serde_json::to_value(&hard).unwrap();
+        save.as_object_mut().unwrap().remove("difficulty");
+        assert_eq!(serde_json::from_value::<App>(save).unwrap().difficulty, Difficulty::Normal);
+        hard.restart();
+        assert_eq!(hard.difficulty, Difficulty::Hard);
+        hard.show_stats();
+        assert!(hard.ordered_messages().any(|msg| msg.content == "Difficulty: hard"));
+    }
+
+    #[test]
+    fn menu_wraps_and_starts_the_game() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let mut app = App::new();
+        app.open_menu();
+        app.menu = vec![MenuItem::NewGame, MenuItem::Continue(2), MenuItem::Quit];
+        assert_eq!(app.state, AppState::Menu);
+
+        app.menu_key(key(KeyCode::Up));
+        assert_eq!(app.menu_key(key(KeyCode::Enter)), CommandResult::Quit);
+        app.menu_key(key(KeyCode::Down));
+        assert_eq!(app.menu[app.menu_selected], MenuItem::NewGame);
+
+        let backend = tui::backend::TestBackend::new(60, 20);
+        let mut terminal = Terminal::new(backend).unwrap();
+        terminal.draw(|f| ui(f, &app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        let screen = (0..buffer.area.height)
+            .map(|y| (0..buffer.area.width).map(|x| buffer.get(x, y).symbol.clone()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert!(screen.contains("> New Game <"));
+        assert!(screen.contains("Continue (slot 2)"));
+
+        let tick = app.tick_count;
+        assert_eq!(app.menu_key(key(KeyCode::Enter)), CommandResult::Continue);
+        assert_eq!((app.state, app.tick_count), (AppState::Game, tick));
+    }
+
+    #[test]
+    fn settings_screen_returns_where_it_was_opened() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let mut app = App::new();
+        app.config.bell = true;
+        app.handle_command("settings");
+        assert_eq!(app.state, AppState::Settings);
+        let screen = SETTINGS.iter().map(|setting| app.setting_enabled(*setting)).collect::<Vec<_>>();
+        assert_eq!(screen, vec![false, true, true, false]);
+
+        app.settings_key(key(KeyCode::Up));
+        assert_eq!(app.settings_selected, SETTINGS.len());
+        app.settings_key(key(KeyCode::Enter));
+        assert_eq!(app.state, AppState::Game);
+
+        app.open_menu();
+        app.open_settings();
+        app.settings_key(key(KeyCode::Esc));
+        assert_eq!(app.state, AppState::Menu);
+    }
+
+    #[test]
+    fn every_command_has_help() {
+        let app = App::new();
+        for command in app.commands.keys() {
+            assert!(app.help.contains_key(command), "missing help for {}", command);
+        }
+    }
+
+    #[test]
+    fn commands_lists_names_and_aliases_in_columns() {
+        let mut app = App::new();
+        app.handle_command("alias zz look");
+        app.handle_command("commands");
+        let rows = app.ordered_messages().rev().take_while(|m| m.color == MessageColor::White).collect::<Vec<_>>();
+        let names = rows.iter().rev().flat_map(|m| m.content.split_whitespace()).collect::<Vec<_>>();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+        assert!(names.contains(&"zz") && names.contains(&"commands"));
+        assert!(rows.iter().all(|m| m.content.split_whitespace().count() <= COMMAND_COLUMNS));
+    }
+
+    #[test]
+    fn aliases_resolve_and_complete() {
+        let words = |list: &[&str]| list.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        let mut app = App::new();
+        app.alias(&words(&["n", "go"]));
+        assert_eq!(app.resolve_alias("n"), "go");
+        assert_eq!(app.resolve_alias("i"), "inventory");
+        assert_eq!(app.resolve_alias("look"), "look");
+
+        app.alias(&words(&["look", "inventory"]));
+        assert_eq!(app.resolve_alias("look"), "look");
+
+        app.input = "i".to_string();
+        assert_eq!(app.get_autocomplete_suggestions(), vec!["inventory"]);
+
+        for short in ["q", "examine", "!!"] {
+            app.alias(&words(&[short, "inventory"]));
+            assert_eq!(app.ordered_messages().next_back().unwrap().content, format!("'{}' is already a command.", short));
+        }
+        assert!(!app.aliases.contains_key("q") && !app.aliases.contains_key("!!"));
+    }
+
+    #[test]
+    fn tab_never_shortens_the_typed_input() {
+        let words = |list: &[&str]| list.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+        let mut app = App::new();
+        app.alias(&words(&["sv", "look"]));
+        app.set_input("s".to_string());
+        assert!(app.get_autocomplete_suggestions().contains(&"look".to_string()));
+        app.complete_input();
+        assert_eq!(app.input, "s");
+    }
+
+    #[test]
+    fn autocomplete_suggests_arguments_after_a_space() {
+        let mut app = App::new();
+        app.go("north");
+        app.input = "go ".to_string();
+        assert_eq!(app.get_autocomplete_suggestions(), vec!["go east", "go north", "go south"]);
+
+        app.input = "Gather S".to_string();
+        app.complete_input();
+        assert_eq!(app.input, "gather sulfur_ore");
+
+        app.input = "craft p".to_string();
+        assert_eq!(app.get_autocomplete_suggestions(), vec!["craft phoenix_beacon", "craft pyrodrill"]);
+        app.input = "craft pyrodrill x".to_string();
+        assert!(app.get_autocomplete_suggestions().is_empty());
+        app.input = "look ".to_string();
+        assert!(app.get_autocomplete_suggestions().is_empty());
+    }
+
+    #[test]
+    fn closest_command_suggests_near_typos() {
+        let app = App::new();
+        assert_eq!(app.closest_command("hlep").map(String::as_str), Some("help"));
+        assert_eq!(app.closest_command("gahter").map(String::as_str), Some("gather"));
+        assert_eq!(app.closest_command("lok").map(String::as_str), Some("look"));
+        assert_eq!(app.closest_command("xyzzy"), None);
+    }
+
+    #[test]
+    fn go_follows_map_connections() {
+        let mut app = App::new();
+        app.go("south");
+        assert_eq!(app.location, "Entrance");
+
+        app.go("north");
+        app.go("east");
+        assert_eq!(app.location, "Ember Fields");
+        app.go("west");
+        assert_eq!(app.location, "Scorched Plains");
+        assert_eq!(app.visited.len(), 3);
+    }
+
+    #[test]
+    fn fire_spreads_to_adjacent_locations() {
+        let mut app = App::new();
+        assert_eq!(app.fire_levels["Forgeflame Ruins"], 0);
+
+        for _ in 0..FIRE_SPREAD_INTERVAL * 3 {
+            app.tick();
+        }
+
+        assert_eq!(app.fire_levels["Inferno Wells"], MAX_FIRE);
+        assert_eq!(app.fire_levels["Forgeflame Ruins"], 3);
+        assert_eq!(app.fire_levels["Pyro Nexus"], 3);
+        assert_eq!(app.fire_levels["Scorched Plains"], 1);
+        assert_eq!(app.fire_levels["Entrance"], 0);
+    }
+
+    #[test]
+    fn lethal_damage_ends_the_game_and_restart_recovers() {
+        let mut app = App::new();
+        app.location = "Inferno Wells".to_string();
+        for _ in 0..HAZARD_INTERVAL * 30 {
+            app.tick();
+        }
+        assert_eq!(app.health, 0);
+        assert_eq!(app.state, AppState::GameOver);
+
+        let (ticks, fires) = (app.tick_count, app.fire_levels.clone());
+        for _ in 0..app.config.ticks_per_hour * 24 {
+            app.tick();
+        }
+        assert_eq!(app.tick_count, ticks);
+        assert_eq!(app.fire_levels, fires);
+
+        app.restart();
+        assert_eq!(app.health, MAX_HEALTH);
+        assert_eq!(app.state, AppState::Game);
+        assert_eq!(app.location, "Entrance");
+    }
+
+    #[test]
+    fn crafting_fails_cleanly_when_short_on_resources() {
+        let mut app = App::new();
+        app.inventory.insert("firestone".to_string(), 10);
+        app.inventory.insert("emberash".to_string(), 4);
+
+        app.craft("blaze_hammer");
+
+        assert_eq!(app.inventory.get("firestone"), Some(&10));
+        assert_eq!(app.inventory.get("emberash"), Some(&4));
+        assert!(!app.inventory.contains_key("blaze_hammer"));
+        assert_eq!(app.messages[app.message_index].content, "Missing: 1 emberash");
+    }
+
+    #[test]
+    fn crafting_with_exactly_enough_consumes_everything() {
+        let mut app = App::new();
+        app.inventory.insert("firestone".to_string(), 10);
+        app.inventory.insert("emberash".to_string(), 5);
+
+        app.craft("blaze_hammer");
+
+        assert!(!app.inventory.contains_key("firestone"));
+        assert!(!app.inventory.contains_key("emberash"));
+        assert_eq!(app.inventory.get("blaze_hammer"), Some(&1));
+    }
+
+    #[test]
+    fn structures_take_resources_upfront_and_finish_over_time() {
+        let mut app = App::new();
+        app.config.ticks_per_hour = 2;
+        app.handle_command("build forge");
+        assert!(app.messages.last().is_some_and(|m| m.content == "The forge needs a finished shelter first."));
+        app.handle_command("build shelter");
+        assert_eq!(app.messages[app.message_index].content, "Missing: 10 emberash, 10 firestone");
+
+        app.inventory.insert("firestone".to_string(), 12);
+        app.inventory.insert("emberash".to_string(), 10);
+        app.handle_command("build shelter");
+        assert_eq!(app.inventory.get("firestone"), Some(&2));
+        assert!(!app.inventory.contains_key("emberash"));
+        assert_eq!(app.constructions[0].ticks_remaining, 4);
+
+        app.tick();
+        app.handle_command("construction");
+        assert_eq!(app.messages[app.message_index].content, "shelter [#####---------------] 25%");
+        for _ in 0..3 {
+            app.tick();
+        }
+        assert!(app.constructions.is_empty());
+        assert!(app.structures.contains("shelter"));
+        assert!(app.events.iter().any(|e| e.content == "The shelter is complete."));
+    }
+
+    #[test]
+    fn trades_exchange_whole_lots_at_fixed_rates() {
+        let mut app = App::new();
+        app.inventory.insert("emberash".to_string(), 7);
+        app.handle_command("trade emberash 6 for ashen_dust");
+        assert_eq!(app.inventory.get("emberash"), Some(&1));
+        assert_eq!(app.inventory.get("ashen_dust"), Some(&2));
+
+        let last = |app: &App| app.messages[app.message_index].content.clone();
+        app.handle_command("trade emberash 3 for ashen_dust");
+        assert_eq!(last(&app), "You only have 1 emberash.");
+        app.handle_command("trade ashen_dust 1 for charcoal_essence");
+        assert_eq!(last(&app), "ashen_dust is traded in lots of 2.");
+        app.handle_command("trade scrap 3 for metal");
+        assert!(last(&app).starts_with("Unknown resource 'scrap'"));
+        app.handle_command("trade ashen_dust 2 for firestone");
+        assert!(last(&app).starts_with("Nobody trades ashen_dust for firestone"));
+        assert_eq!(app.messages[app.message_index].color, MessageColor::Red);
+    }
+
+    #[test]
+    fn scripted_commands_share_the_interactive_dispatch() {
+        let mut app = App::new();
+        run_commands(&mut app, &["GATHER firestone", "go north", "i", "hlep", "quit"]);
+
+        assert!(app.inventory.contains_key("firestone"));
+        assert_eq!(app.location, "Scorched Plains");
+        assert!(app.quit_armed);
+        assert_eq!(app.history, vec!["GATHER firestone", "go north", "i", "hlep", "quit"]);
+        assert_eq!(app.handle_command("quit"), CommandResult::Quit);
+    }
+
+    #[test]
+    fn run_executes_a_script_without_endless_recursion() {
+        let path = temp_path("run");
+        let path = path.to_str().unwrap();
+        fs::write(path, format!("# warm up\ngather firestone\n\nrun {}\n", path)).unwrap();
+        let mut app = App::new();
+        assert_eq!(app.handle_command(&format!("run {}", path)), CommandResult::Continue);
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(u64::from(app.inventory["firestone"]), app.resources_gathered);
+        assert_eq!(app.run_depth, 0);
+        assert_eq!(app.undo_stack.len(), MAX_RUN_DEPTH);
+        let echoes = app.messages.iter().filter(|m| m.content == "gather firestone" && m.dim).count();
+        assert_eq!(echoes, MAX_RUN_DEPTH);
+        assert_eq!(app.history, vec![format!("run {}", path)]);
+        assert!(app.messages.iter().any(|m| m.content.contains("scripts can only nest 3 deep")));
+    }
+
+    #[test]
+    fn scripts_skip_commands_that_need_confirmation() {
+        let path = temp_path("run_confirm");
+        let path = path.to_str().unwrap();
+        fs::write(path, "gather firestone\nclear\ny\nrestart\ngather firestone\n").unwrap();
+        let mut app = App::new();
+        app.handle_command(&format!("run {}", path));
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(app.pending_confirm, None);
+        assert_eq!(u64::from(app.inventory["firestone"]), app.resources_gathered);
+        assert_eq!(app.messages.iter().filter(|m| m.content.starts_with("Skipping")).count(), 2);
+        assert!(app.messages.iter().any(|m| m.content == "Unknown command. Type 'help' for commands."));
+    }
+
+    #[test]
+    fn undo_inside_a_script_keeps_the_run_depth() {
+        let path = temp_path("run_undo");
+        let path = path.to_str().unwrap();
+        fs::write(path, "gather firestone\nundo\n").unwrap();
+        let mut app = App::new();
+        app.handle_command(&format!("run {}", path));
+        app.handle_command(&format!("run {}", path));
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(app.run_depth, 0);
+        assert_eq!(app.inventory.get("firestone"), None);
+        assert!(!app.messages.iter().any(|m| m.content.contains("scripts can only nest")));
+    }
+
+    #[test]
+    fn confirmed_commands_cannot_be_undone() {
+        let yes = KeyEvent::new(KeyCode::Char('y'), KeyModifiers::NONE);
+        let mut app = App::new();
+        run_commands(&mut app, &["gather firestone", "gather emberash", "drop all"]);
+        app.answer_confirm(yes);
+        assert!(app.inventory.is_empty());
+        app.handle_command("undo");
+        assert!(app.inventory.is_empty());
+        assert_eq!(app.ordered_messages().next_back().unwrap().content, "Nothing to undo.");
+
+        run_commands(&mut app, &["gather firestone", "clear"]);
+        app.answer_confirm(yes);
+        assert!(app.undo_stack.is_empty());
+    }
+
+    #[test]
+    fn undo_after_restarting_brings_nothing_back() {
+        let mut app = App::new();
+        run_commands(&mut app, &["gather firestone", "gather firestone"]);
+        app.damage(MAX_HEALTH);
+        assert_eq!(app.state, AppState::GameOver);
+        run_commands(&mut app, &["restart", "undo"]);
+
+        assert_eq!(app.state, AppState::Game);
+        assert_eq!(app.health, MAX_HEALTH);
+        assert!(app.inventory.is_empty());
+        assert_eq!(app.ordered_messages().next_back().unwrap().content, "Nothing to undo.");
+    }
+
+    #[test]
+    fn undo_while_paused_stays_paused() {
+        let mut app = App::new();
+        run_commands(&mut app, &["pause", "gather firestone", "undo"]);
+        assert!(app.paused);
+        assert_eq!(app.inventory.get("firestone"), None);
+    }
+
+    #[test]
+    fn undo_keeps_settings_changed_since_the_snapshot() {
+        let mut app = App::new();
+        let minimap = app.show_minimap;
+        run_commands(&mut app, &["gather firestone", "theme mono", "map", "filter green", "undo"]);
+        assert_eq!(app.inventory.get("firestone"), None);
+        assert_eq!(app.theme.name, "mono");
+        assert_eq!(app.show_minimap, !minimap);
+        assert_eq!(app.filter, Some(MessageColor::Green));
+    }
+
+    #[test]
+    fn achievements_unlock_once_and_persist() {
+        let mut app = App::new();
+        run_commands(&mut app, &["gather firestone", "gather firestone"]);
+        let unlocks = app.events.iter().filter(|m| m.content == "Achievement unlocked: First Gather").collect::<Vec<_>>();
+        assert_eq!(unlocks.len(), 1);
+        assert!(unlocks[0].bold);
+        assert_eq!(unlocks[0].color, MessageColor::Yellow);
+
+        while app.game_time().0 < 3 {
+            app.tick_count += app.config.ticks_per_hour;
+        }
+        app.tick();
+        assert!(app.achievements.contains("Survivor"));
+
+        let loaded: App = serde_json::from_str(&serde_json::to_string(&app).unwrap()).unwrap();
+        assert_eq!(loaded.achievements.len(), 2);
+    }
+
+    #[test]
+    fn no_achievements_are_earned_after_death() {
+        let mut app = App::new();
+        app.damage(MAX_HEALTH);
+        assert_eq!(app.state, AppState::GameOver);
+        while app.game_time().0 < 3 {
+            app.tick_count += app.config.ticks_per_hour;
+        }
+        app.tick();
+        app.handle_command("help");
+        assert!(!app.achievements.contains("Survivor"));
+    }
+
+    #[test]
+    fn game_events_go_to_their_own_log() {
+        let mut app = App::new();
+        app.config.ticks_per_hour = 1;
+        app.tick_count = 23;
+        app.tick();
+        assert_eq!(app.events.last().map(|e| e.content.as_str()), Some("Day 2 dawns."));
+
+        app.spread_fire();
+        assert!(app.events.last().is_some_and(|e| e.content.starts_with("The fire spreads to ")));
+        assert!(!app.messages.iter().any(|m| m.content.contains("dawns") || m.content.contains("spreads")));
+
+        for _ in 0..MAX_EVENTS {
+            app.add_event("Smoke", MessageColor::Red);
+        }
+        assert_eq!(app.events.len(), MAX_EVENTS);
+    }
+
+    #[test]
+    fn version_reports_the_crate_version() {
+        let mut app = App::new();
+        app.handle_command("version");
+        let message = app.messages.last().unwrap();
+        assert!(message.content.starts_with(&format!("Pyrobase {}", env!("CARGO_PKG_VERSION"))));
+        assert_eq!(message.color, MessageColor::Cyan);
+    }
+
+    #[test]
+    fn each_new_day_starts_with_a_summary_of_the_last() {
+        let mut app = App::new();
+        app.config.ticks_per_hour = 1;
+        run_commands(&mut app, &["gather firestone", "go north", "go south"]);
+        app.damage(15);
+        app.tick_count = 23;
+        app.tick();
+
+        let summary = &app.events[app.events.len() - 2];
+        let gathered = app.resources_gathered;
+        assert_eq!(
+            summary.content,
+            format!("═══ Day 1: gathered {}, visited 2 locations, fire +0, health -15 ═══", gathered),
+        );
+        assert!(summary.bold);
+        assert!(app.today.visited.is_empty());
+        assert_eq!(app.today.health_before, MAX_HEALTH - 15);
+    }
+
+    #[test]
+    fn look_lists_exits_in_direction_order() {
+        let mut app = App::new();
+        app.location = "Scorched Plains".to_string();
+        app.handle_command("look");
+        let last = app.ordered_messages().next_back().unwrap();
+        assert_eq!((last.content.as_str(), last.color), ("Exits: north, south, east.", MessageColor::Gray));
+
+        app.location = "Nowhere".to_string();
+        app.handle_command("look");
+        assert_eq!(app.messages[app.message_index].content, "This is a dead end.");
+    }
+
+    #[test]
+    fn reveal_needs_cheats_enabled() {
+        let mut app = App::new();
+        app.handle_command("reveal");
+        assert!(app.messages.iter().any(|m| m.content.starts_with("Unknown command.")));
+        assert_eq!(app.visited.len(), 1);
+
+        app.cheats = true;
+        app.handle_command("reveal");
+        assert_eq!(app.visited.len(), app.map.len());
+        assert!(!app.commands.contains_key("reveal"));
+    }
+
+    #[test]
+    fn cmdhistory_numbers_the_latest_commands() {
+        let mut app = App::new();
+        run_commands(&mut app, &["look", "inventory", "map", "cmdhistory 2"]);
+        let listed = app.ordered_messages().rev().take(3).map(|m| m.content.clone()).collect::<Vec<_>>();
+        assert_eq!(listed, vec!["   4  cmdhistory 2", "   3  map", "Last 2 commands:"]);
+
+        app.handle_command("cmdhistory zero");
+        assert_eq!(app.messages[app.message_index].content, "'zero' is not a valid number.");
+    }
+
+    #[test]
+    fn notes_keep_their_case_and_can_be_cleared() {
+        let mut app = App::new();
+        run_commands(&mut app, &["note Check the Forgeflame Ruins", "note \"bring   water\""]);
+        assert_eq!(app.notes, vec!["Check the Forgeflame Ruins", "bring   water"]);
+
+        app.handle_command("note CLEAR");
+        assert!(app.notes.is_empty());
+    }
+
+    #[test]
+    fn compose_saves_multi_line_entries() {
+        let mut app = App::new();
+        app.resize(80, 30);
+        let normal_height = app.page_height;
+        app.handle_command("compose");
+        assert!(app.composing);
+        assert!(app.page_height < normal_height);
+
+        let press = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        for code in [KeyCode::Char('a'), KeyCode::Enter, KeyCode::Char('b'), KeyCode::Enter] {
+            app.compose_key(press(code));
+        }
+        app.compose_key(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL));
+        assert!(!app.composing);
+        assert_eq!(app.page_height, normal_height);
+        assert_eq!(app.notes, vec!["a\nb"]);
+
+        app.handle_command("compose");
+        app.compose_key(press(KeyCode::Char('x')));
+        app.compose_key(press(KeyCode::Esc));
+        assert_eq!(app.notes.len(), 1);
+        assert!(app.input.is_empty());
+
+        app.handle_command("notes");
+        let log = app.ordered_messages().rev().take(2).map(|m| m.content.clone()).collect::<Vec<_>>();
+        assert_eq!(log, vec!["   b", "1. a"]);
+    }
+
+    #[test]
+    fn search_lists_recent_matches_with_their_index() {
+        let mut app = App::new();
+        for i in 0..30 {
+            app.add_message(&format!("Ember {}", i), MessageColor::Red);
+        }
+        app.handle_command("search EMBER");
+        let results = app.ordered_messages().rev().take(MAX_SEARCH_RESULTS).collect::<Vec<_>>();
+        assert_eq!(results[0].content, "[31] Ember 29");
+        assert_eq!(results[MAX_SEARCH_RESULTS - 1].content, "[12] Ember 10");
+
+        app.handle_command("search nothing here");
+        assert_eq!(app.messages.last().map(|m| m.content.as_str()), Some("No matches for 'nothing here'."));
+    }
+
+    #[test]
+    fn playtime_accumulates_across_saves() {
+        assert_eq!(format_duration(0), "00:00:00");
+        assert_eq!(format_duration(3 * 3600 + 25 * 60 + 7), "03:25:07");
+        assert_eq!(format_duration(100 * 3600), "100:00:00");
+
+        let mut app = App::new();
+        app.played_secs = 90;
+        app.playtime_since -= Duration::from_secs(30);
+        app.sync_playtime();
+        assert_eq!(app.played_secs, 120);
+        assert_eq!(app.total_playtime(), 120);
+
+        let loaded: App = serde_json::from_str(&serde_json::to_string(&app).unwrap()).unwrap();
+        assert_eq!(loaded.played_secs, 120);
+    }
+
+    #[test]
+    fn undo_reverts_only_state_changes() {
+        let mut app = App::new();
+        run_commands(&mut app, &["gather firestone", "look", "inventory"]);
+        let gathered = app.inventory["firestone"];
+        app.handle_command("go north");
+        assert_eq!(app.undo_stack.len(), 2);
+
+        app.handle_command("undo");
+        assert_eq!(app.location, "Entrance");
+        assert_eq!(app.inventory.get("firestone"), Some(&gathered));
+        assert_eq!(app.undo_stack.len(), 1);
+
+        run_commands(&mut app, &["undo", "undo"]);
+        assert!(app.inventory.is_empty());
+        assert!(app.undo_stack.is_empty());
+        assert_eq!(app.messages.last().map(|m| m.content.as_str()), Some("Nothing to undo."));
+
+        for _ in 0..MAX_UNDO + 3 {
+            app.handle_command("gather emberash");
+        }
+        assert_eq!(app.undo_stack.len(), MAX_UNDO);
+    }
+
+    #[test]
+    fn only_state_changing_commands_take_snapshots() {
+        let mut app = App::new();
+        run_commands(&mut app, &["look", "help", "stats", "inventory", "go nowhere"]);
+        assert!(app.undo_stack.is_empty());
+        app.handle_command("go north");
+        assert_eq!(app.undo_stack.len(), 1);
+    }
+
+    #[test]
+    fn minimap_reveals_rooms_as_they_are_explored() {
+        let mut app = App::new();
+        assert_eq!(app.minimap_lines(), vec!["   ", "   ", "   ", "   ", "?  ", "|  ", "@  "]);
+
+        run_commands(&mut app, &["go north", "go east"]);
+        assert_eq!(app.minimap_lines(), vec!["   ", "   ", "?  ", "|  ", "#-@", "|  ", "#  "]);
+
+        app.handle_command("map");
+        assert!(!app.show_minimap);
+    }
+
+    #[test]
+    fn no_color_mode_drops_colors_and_blinking() {
+        let mut app = App::new();
+        app.handle_command("theme highcontrast");
+        app.no_color = true;
+        let theme = app.display_theme();
+        assert_eq!(theme.message_color(MessageColor::Rgb(255, 120, 0)), Color::Reset);
+        assert_eq!(theme.text(), Style::default().fg(Color::Reset).bg(Color::Reset));
+        assert!(!theme.cursor().add_modifier.contains(Modifier::SLOW_BLINK));
+        assert_eq!(app.theme.name, "highcontrast");
+    }
+
+    #[test]
+    fn keybindings_translate_to_the_builtin_keys() {
+        assert_eq!(parse_key("Ctrl-P"), Some((KeyCode::Char('p'), KeyModifiers::CONTROL)));
+        assert_eq!(parse_key("f2"), Some((KeyCode::F(2), KeyModifiers::NONE)));
+        assert_eq!(parse_key("f13"), None);
+        assert_eq!(parse_key("ctrl-"), None);
+
+        let bindings: Keybindings = toml::from_str("history_up = \"ctrl-p\"").unwrap();
+        assert_eq!(bindings.submit, "enter");
+        let ctrl_p = KeyEvent::new(KeyCode::Char('p'), KeyModifiers::CONTROL);
+        assert_eq!(bindings.translate(ctrl_p).code, KeyCode::Up);
+        let typed = KeyEvent::new(KeyCode::Char('p'), KeyModifiers::NONE);
+        assert_eq!(bindings.translate(typed), typed);
+
+        let broken: Keybindings = toml::from_str("submit = \"hyper-x\"").unwrap();
+        assert!(broken.validate().is_err());
+    }
+
+    #[test]
+    fn destructive_commands_wait_for_a_yes() {
+        let mut app = App::new();
+        app.gather("firestone");
+        app.handle_command("drop all");
+        assert_eq!(app.pending_confirm.as_deref(), Some("drop all"));
+        app.handle_command("restart");
+        assert!(app.messages.last().is_some_and(|m| m.content.starts_with("Answer y or n")));
+
+        app.answer_confirm(KeyEvent::new(KeyCode::Char('n'), KeyModifiers::NONE));
+        assert!(app.pending_confirm.is_none());
+        assert!(app.inventory.contains_key("firestone"));
+
+        app.handle_command("drop all");
+        app.answer_confirm(KeyEvent::new(KeyCode::Char('y'), KeyModifiers::NONE));
+        assert!(app.inventory.is_empty());
+        assert!(!app.confirmed);
+
+        app.handle_command("look");
+        assert!(app.pending_confirm.is_none());
+    }
+
+    #[test]
+    fn cursor_style_follows_the_config() {
+        let mut app = App::new();
+        let theme = app.display_theme();
+        let cursor = app.cursor_span(&theme);
+        assert_eq!(cursor.content, "_");
+        assert!(cursor.style.add_modifier.contains(Modifier::SLOW_BLINK));
+
+        app.config = toml::from_str("cursor = \"block\"\ncursor_blink = false").unwrap();
+        let cursor = app.cursor_span(&theme);
+        assert_eq!(cursor.content, "█");
+        assert!(!cursor.style.add_modifier.contains(Modifier::SLOW_BLINK));
+        assert!(CURSOR_STYLES.iter().all(|name| CursorStyle::by_name(name).is_some_and(|style| style.name() == *name)));
+        assert!(toml::to_string(&app.config).unwrap().contains("cursor = \"block\""));
+    }
+
+    #[test]
+    fn gauges_are_colored_by_how_full_they_are() {
+        assert_eq!(threshold_color(MAX_HEALTH, MAX_HEALTH), MessageColor::Green);
+        assert_eq!(threshold_color(60, 100), MessageColor::Green);
+        assert_eq!(threshold_color(59, 100), MessageColor::Yellow);
+        assert_eq!(threshold_color(29, 100), MessageColor::Red);
+        assert_eq!(threshold_color(0, 0), MessageColor::Red);
+    }
+
+    #[test]
+    fn idle_polling_backs_off() {
+        let until_tick = Duration::from_millis(30);
+        assert_eq!(poll_timeout(Duration::from_secs(1), until_tick), until_tick);
+        assert_eq!(poll_timeout(IDLE_AFTER, until_tick), IDLE_POLL);
+    }
+
+    #[test]
+    fn tick_clock_carries_leftover_time() {
+        let start = Instant::now();
+        let ms = Duration::from_millis;
+        let mut clock = TickClock::new(ms(50), start);
+        assert_eq!(clock.due(start + ms(49)), 0);
+        assert_eq!(clock.until_next(start + ms(49)), ms(1));
+        assert_eq!(clock.due(start + ms(125)), 2);
+        // The 25ms left over counts toward the next tick
+        assert_eq!(clock.until_next(start + ms(125)), ms(25));
+        assert_eq!(clock.due(start + ms(150)), 1);
+        assert_eq!(clock.due(start + ms(150) + IDLE_POLL), 10);
+        assert_eq!(clock.due(start), 0);
+    }
+
+    #[test]
+    fn tiny_terminals_get_a_resize_notice() {
+        let render = |width, height| {
+            let mut terminal = Terminal::new(tui::backend::TestBackend::new(width, height)).unwrap();
+            terminal.draw(|f| ui(f, &App::new())).unwrap();
+            let buffer = terminal.backend().buffer().clone();
+            buffer.content.iter().map(|cell| cell.symbol.clone()).collect::<String>()
+        };
+        assert!(render(30, 8).contains("Terminal too small"));
+        let compact = render(MIN_TERMINAL_WIDTH, MIN_TERMINAL_HEIGHT);
+        assert!(!compact.contains("Terminal too small"));
+        assert!(compact.contains("Messages") && compact.contains("Input"));
+        assert!(!compact.contains("Events"));
+        let normal = render(MIN_TERMINAL_WIDTH, FULL_LAYOUT_HEIGHT);
+        assert!(normal.contains("Events") && normal.contains("Input"));
+    }
+
+    #[test]
+    fn esc_and_ctrl_c_are_quit_keys_outside_other_modes() {
+        let mut app = App::new();
+        let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert!(app.is_quit_key(esc) && app.is_quit_key(ctrl_c));
+        assert!(!app.is_quit_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::NONE)));
+
+        app.handle_command("compose");
+        assert!(!app.is_quit_key(esc));
+        assert!(app.is_quit_key(ctrl_c));
+    }
+
+    #[test]
+    fn failed_commands_flash_the_input_border() {
+        let mut app = App::new();
+        app.handle_command("look");
+        assert!(!app.error_flash());
+
+        // The flash runs on the UI clock, so it fades even while paused
+        app.handle_command("pause");
+        app.handle_command("frobnicate");
+        assert!(app.error_flash());
+        for _ in 0..ERROR_FLASH_TICKS {
+            assert!(!app.ui_tick());
+        }
+        assert!(app.error_flash());
+        assert!(app.ui_tick());
+        assert!(!app.error_flash());
+
+        app.handle_command("go sideways");
+        assert!(app.error_flash());
+    }
+
+    #[test]
+    fn bell_rings_on_critical_health_only_when_enabled() {
+        let mut app = App::new();
+        app.damage(MAX_HEALTH - CRITICAL_HEALTH);
+        assert!(!app.bell_pending);
+
+        let mut app = App::new();
+        app.config.bell = true;
+        app.damage(MAX_HEALTH - CRITICAL_HEALTH - 1);
+        assert!(!app.bell_pending);
+        app.damage(1);
+        assert!(app.bell_pending);
+
+        app.bell_pending = false;
+        app.damage(1);
+        assert!(!app.bell_pending);
+        app.gather("firestone");
+        app.check_achievements();
+        assert!(app.bell_pending);
+    }
+
+    #[test]
+    fn typewriter_reveals_messages_over_ticks() {
+        let mut app = App::new();
+        app.config.typewriter = true;
+        app.add_message("{red:Hot} coals", MessageColor::White);
+        let msg = app.ordered_messages().next_back().unwrap();
+        assert_eq!(msg.hidden, "Hot coals".len());
+        let spans = parse_markup(&app.format_message(msg), Style::default(), &Theme::default());
+        let shown: String = drop_trailing_chars(spans, 7).iter().map(|span| span.content.as_ref()).collect();
+        assert_eq!(shown, "> Ho");
+
+        app.tick();
+        assert_eq!(app.ordered_messages().next_back().unwrap().hidden, 9 - TYPEWRITER_CHARS_PER_TICK);
+        for _ in 0..3 {
+            app.tick();
+        }
+        assert_eq!(app.ordered_messages().next_back().unwrap().hidden, 0);
+
+        // A burst of messages is shown straight away instead of queueing up
+        for i in 0..=TYPEWRITER_MAX_PENDING {
+            app.add_message(&format!("line {}", i), MessageColor::White);
+        }
+        assert!(app.messages.iter().all(|msg| msg.hidden == 0));
+    }
+
+    #[test]
+    fn critical_health_alert_has_a_background() {
+        let mut app = App::new();
+        app.damage(MAX_HEALTH - CRITICAL_HEALTH);
+        let alert = app.ordered_messages().next_back().unwrap();
+        assert_eq!(alert.content, "Your health is critical!");
+        assert_eq!(alert.bg, Some(MessageColor::Red));
+        let style = Theme::default().message_style(alert);
+        assert_eq!((style.fg, style.bg), (Some(Color::White), Some(Color::Red)));
+        assert_eq!(Theme::by_name("mono").unwrap().message_style(alert).bg, None);
+
+        // Saves from before backgrounds still load
+        let old: StoredMessage = serde_json::from_str(r#"{"content":"hi","color":"White"}"#).unwrap();
+        assert_eq!(old.bg, None);
+    }
+
+    #[test]
+    fn quit_requires_confirmation_when_dirty() {
+        let mut app = App::new();
+        assert!(app.request_quit());
+
+        app.gather("emberash");
+        assert!(!app.request_quit());
+        assert!(app.request_quit());
+    }
+}